@@ -1,60 +1,473 @@
 use std::{
-    fs::{self, File},
-    io::{self, Write},
+    borrow::Cow,
+    cell::RefCell,
+    fs::{self, File, Metadata, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
 };
 
+use ropey::{Rope, RopeSlice};
+use unicode_segmentation::UnicodeSegmentation;
+
 use crate::highlighter::FileType;
+use crate::history::{Edit, History};
+
+/// How an edit changed the lines of the buffer, so caches keyed by line
+/// index can follow along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Modified(usize),
+    /// `count` new lines now start at line `at`.
+    Inserted {
+        at: usize,
+        count: usize,
+    },
+    /// The `count` lines starting at line `at` are gone.
+    Removed {
+        at: usize,
+        count: usize,
+    },
+}
+
+/// A location in the buffer, as a line index and a byte offset into that line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub line: usize,
+    pub byte: usize,
+}
+
+impl Position {
+    /// Position just past `text` when it is inserted at `at`.
+    pub fn after(at: Position, text: &str) -> Position {
+        match text.rfind('\n') {
+            Some(last_newline) => Position {
+                line: at.line + text.matches('\n').count(),
+                byte: text.len() - last_newline - 1,
+            },
+            None => Position {
+                line: at.line,
+                byte: at.byte + text.len(),
+            },
+        }
+    }
+}
 
 pub struct Buffer {
-    pub lines: Vec<String>,
+    text: Rope,
     pub file_name: Option<String>,
     pub file_type: FileType,
+    // the file type was picked by hand, so saving keeps it
+    file_type_overridden: bool,
     pub modified: bool,
+    /// Keep the previous version of the file as `name~` when saving.
+    pub backup: bool,
+    pub history: History,
+    // line changes not yet picked up with `take_changes`
+    changes: Vec<LineChange>,
+    // grapheme byte offsets of the most recently measured line
+    graphemes: RefCell<Option<(usize, Vec<usize>)>>,
 }
 
-impl Buffer {
-    pub fn default() -> Self {
+impl Default for Buffer {
+    fn default() -> Self {
         Buffer {
-            lines: Vec::new(),
+            text: Rope::new(),
             file_name: None,
-            file_type: FileType::PlainText,
+            file_type: FileType::default(),
+            file_type_overridden: false,
             modified: false,
+            backup: false,
+            history: History::default(),
+            changes: Vec::new(),
+            graphemes: RefCell::new(None),
         }
     }
+}
 
-    pub fn push(&mut self, val: String) -> Result<(), io::Error> {
-        self.lines.push(val);
-        Ok(())
-    }
-
+impl Buffer {
     pub fn load_lines_from_file(&mut self, file_name: String) -> Result<(), io::Error> {
-        self.file_type = FileType::from_filename(&file_name);
-        self.file_name = Some(file_name.to_owned());
-        let file = fs::read_to_string(file_name)?;
+        let mut file = fs::read_to_string(&file_name)?;
 
-        for line in file.lines() {
-            self.push(line.to_owned())?;
+        if file.contains("\r\n") {
+            file = file.replace("\r\n", "\n");
+        }
+        // the final line terminator is added back on save
+        if file.ends_with('\n') {
+            file.pop();
         }
+
+        self.text = Rope::from_str(&file);
+        self.file_type = FileType::detect(&file_name, self);
+        self.file_type_overridden = false;
+        self.file_name = Some(file_name);
+        self.graphemes.replace(None);
+        self.history.clear();
+        self.changes.clear();
+        self.modified = false;
         Ok(())
     }
 
+    /// Sets the file type by hand. Saving no longer re-detects it.
+    pub fn set_file_type(&mut self, file_type: FileType) {
+        self.file_type = file_type;
+        self.file_type_overridden = true;
+    }
+
+    /// Detects the file type from the file name and contents again,
+    /// dropping one set by hand.
+    pub fn detect_file_type(&mut self) {
+        let file_name = self.file_name.as_deref().unwrap_or_default();
+        self.file_type = FileType::detect(file_name, self);
+        self.file_type_overridden = false;
+    }
+
     pub fn clear_buffer(&mut self) {
-        self.lines.clear();
+        self.text = Rope::new();
+        self.graphemes.replace(None);
+        self.history.clear();
+        self.changes.clear();
+        self.modified = false;
     }
 
     pub fn save_buffer_as_file(&mut self) -> Result<(), io::Error> {
-        if let Some(path) = &self.file_name {
-            let mut file = File::create(path)?;
-            self.file_type = FileType::from_filename(path);
-            for line in &self.lines {
-                writeln!(file, "{}", line)?;
-            }
+        match self.file_name.clone() {
+            Some(path) => self.save_as(&path),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer has no file name",
+            )),
         }
+    }
+
+    /// Writes the buffer to `path`, which becomes its file name from then on.
+    ///
+    /// The text goes to a temporary file next to the target, which is synced
+    /// and then renamed over it, so a failed save never leaves a truncated
+    /// file behind. The target keeps its permissions and, where allowed, its
+    /// owner; with `backup` set the previous version is kept as `path~`.
+    pub fn save_as(&mut self, path: &str) -> Result<(), io::Error> {
+        let target = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let existing = fs::metadata(&target).ok();
 
+        let Some(name) = target.file_name() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a file path",
+            ));
+        };
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let temp_path = dir.join(format!(
+            ".{}.editra-{}.tmp",
+            name.to_string_lossy(),
+            process::id()
+        ));
+
+        let result = self
+            .write_temp_file(&temp_path, existing.as_ref())
+            .and_then(|()| {
+                if self.backup && existing.is_some() {
+                    let mut backup_path = target.clone().into_os_string();
+                    backup_path.push("~");
+                    fs::copy(&target, backup_path)?;
+                }
+                fs::rename(&temp_path, &target)?;
+                Self::sync_directory(dir)
+            });
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result?;
+
+        self.file_name = Some(path.to_owned());
+        if !self.file_type_overridden {
+            self.file_type = FileType::detect(path, self);
+        }
+        self.history.mark_saved();
+        self.modified = false;
+        Ok(())
+    }
+
+    fn write_temp_file(
+        &self,
+        temp_path: &Path,
+        existing: Option<&Metadata>,
+    ) -> Result<(), io::Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)?;
+        if let Some(metadata) = existing {
+            Self::copy_metadata(&file, metadata)?;
+        }
+
+        let mut writer = BufWriter::new(file);
+        if !self.is_empty() {
+            self.text.write_to(&mut writer)?;
+            writeln!(writer)?;
+        }
+
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()
+    }
+
+    #[cfg(unix)]
+    fn copy_metadata(file: &File, metadata: &Metadata) -> Result<(), io::Error> {
+        use std::os::unix::fs::{MetadataExt, fchown};
+
+        // Only root may hand a file to another user, so fall back to keeping
+        // just the group, and then to our own ownership.
+        if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+            let _ = fchown(file, None, Some(metadata.gid()));
+        }
+        // after chown, which may clear setuid/setgid bits
+        file.set_permissions(metadata.permissions())
+    }
+
+    #[cfg(not(unix))]
+    fn copy_metadata(file: &File, metadata: &Metadata) -> Result<(), io::Error> {
+        file.set_permissions(metadata.permissions())
+    }
+
+    // Makes the rename itself durable.
+    #[cfg(unix)]
+    fn sync_directory(dir: &Path) -> Result<(), io::Error> {
+        File::open(dir)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_directory(_dir: &Path) -> Result<(), io::Error> {
         Ok(())
     }
 
     pub fn is_empty(&self) -> bool {
-        self.lines.is_empty()
+        self.text.len_bytes() == 0
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.len_lines()
+    }
+
+    /// Contents of the line at `line_idx`, without its line terminator.
+    pub fn line(&self, line_idx: usize) -> Option<Cow<'_, str>> {
+        if line_idx >= self.line_count() {
+            return None;
+        }
+        Some(Self::trim_newline(self.text.line(line_idx)).into())
+    }
+
+    /// Lines starting at `line_idx`, without their line terminators.
+    pub fn lines_at(&self, line_idx: usize) -> impl Iterator<Item = Cow<'_, str>> {
+        let line_idx = line_idx.min(self.line_count());
+        // ropey yields an empty line when starting at the very end
+        self.text
+            .lines_at(line_idx)
+            .take(self.line_count() - line_idx)
+            .map(|line| Self::trim_newline(line).into())
+    }
+
+    /// Length of the line at `line_idx` in bytes.
+    pub fn line_len(&self, line_idx: usize) -> usize {
+        if line_idx >= self.line_count() {
+            return 0;
+        }
+        Self::trim_newline(self.text.line(line_idx)).len_bytes()
+    }
+
+    /// Moves `position` back inside the document.
+    pub fn clamp(&self, position: Position) -> Position {
+        let last = self.line_count() - 1;
+        if position.line > last {
+            return Position {
+                line: last,
+                byte: self.line_len(last),
+            };
+        }
+        Position {
+            line: position.line,
+            byte: position.byte.min(self.line_len(position.line)),
+        }
+    }
+
+    pub fn grapheme_count(&self, line_idx: usize) -> usize {
+        self.with_graphemes(line_idx, |offsets| offsets.len())
+    }
+
+    /// Byte offset of the `grapheme_idx`-th grapheme, clamped to the line end.
+    pub fn byte_index(&self, line_idx: usize, grapheme_idx: usize) -> usize {
+        let line_len = self.line_len(line_idx);
+        self.with_graphemes(line_idx, |offsets| {
+            offsets.get(grapheme_idx).copied().unwrap_or(line_len)
+        })
+    }
+
+    pub fn grapheme_index(&self, line_idx: usize, byte_idx: usize) -> usize {
+        self.with_graphemes(line_idx, |offsets| {
+            offsets.partition_point(|&offset| offset < byte_idx)
+        })
+    }
+
+    /// Line changes made since the last call, oldest first.
+    pub fn take_changes(&mut self) -> Vec<LineChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Text between `start` and `end`, with lines joined by `\n`.
+    pub fn slice(&self, start: Position, end: Position) -> String {
+        self.text
+            .slice(self.char_index(start)..self.char_index(end))
+            .to_string()
+    }
+
+    /// Inserts `text` at `at` and returns the position just past it.
+    pub fn insert(&mut self, at: Position, text: &str) -> Position {
+        if text.is_empty() {
+            return at;
+        }
+        let end = self.apply_insert(at, text);
+        self.history.record(
+            Edit::Insert {
+                at,
+                text: text.to_owned(),
+            },
+            at,
+            end,
+        );
+        self.modified = !self.history.is_at_save_point();
+        end
+    }
+
+    /// Removes the text between `start` and `end` and returns it. `cursor` is
+    /// where the cursor sat before the deletion, restored on undo.
+    pub fn delete(&mut self, start: Position, end: Position, cursor: Position) -> String {
+        let text = self.apply_delete(start, end);
+        if !text.is_empty() {
+            self.history.record(
+                Edit::Delete {
+                    at: start,
+                    text: text.clone(),
+                },
+                cursor,
+                start,
+            );
+            self.modified = !self.history.is_at_save_point();
+        }
+        text
+    }
+
+    /// Replaces the text between `start` and `end` with `text` as a single
+    /// undo step and returns the position just past the new text.
+    pub fn replace(
+        &mut self,
+        start: Position,
+        end: Position,
+        text: &str,
+        cursor: Position,
+    ) -> Position {
+        self.history.begin_group();
+        self.delete(start, end, cursor);
+        let new_end = self.insert(start, text);
+        self.history.end_group();
+        new_end
+    }
+
+    /// Reverts the last undo step. Returns the cursor to restore.
+    pub fn undo(&mut self) -> Option<Position> {
+        let transaction = self.history.pop_undo()?;
+        for edit in transaction.edits.iter().rev() {
+            match edit {
+                Edit::Insert { at, .. } => {
+                    self.apply_delete(*at, edit.end());
+                }
+                Edit::Delete { at, text } => {
+                    self.apply_insert(*at, text);
+                }
+            }
+        }
+        self.modified = !self.history.is_at_save_point();
+        Some(transaction.cursor_before)
+    }
+
+    /// Reapplies the last undone step. Returns the cursor to restore.
+    pub fn redo(&mut self) -> Option<Position> {
+        let transaction = self.history.pop_redo()?;
+        for edit in &transaction.edits {
+            match edit {
+                Edit::Insert { at, text } => {
+                    self.apply_insert(*at, text);
+                }
+                Edit::Delete { at, .. } => {
+                    self.apply_delete(*at, edit.end());
+                }
+            }
+        }
+        self.modified = !self.history.is_at_save_point();
+        Some(transaction.cursor_after)
+    }
+
+    fn apply_insert(&mut self, at: Position, text: &str) -> Position {
+        let char_idx = self.char_index(at);
+        self.text.insert(char_idx, text);
+        self.graphemes.replace(None);
+
+        let end = Position::after(at, text);
+        self.changes.push(LineChange::Modified(at.line));
+        if end.line > at.line {
+            self.changes.push(LineChange::Inserted {
+                at: at.line + 1,
+                count: end.line - at.line,
+            });
+        }
+        end
+    }
+
+    fn apply_delete(&mut self, start: Position, end: Position) -> String {
+        let range = self.char_index(start)..self.char_index(end);
+        let removed = self.text.slice(range.clone()).to_string();
+        self.text.remove(range);
+        self.graphemes.replace(None);
+
+        if end.line > start.line {
+            self.changes.push(LineChange::Removed {
+                at: start.line + 1,
+                count: end.line - start.line,
+            });
+        }
+        self.changes.push(LineChange::Modified(start.line));
+
+        removed
+    }
+
+    fn char_index(&self, position: Position) -> usize {
+        let line_start = self.text.line_to_byte(position.line);
+        self.text.byte_to_char(line_start + position.byte)
+    }
+
+    fn trim_newline(line: RopeSlice<'_>) -> RopeSlice<'_> {
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            line.slice(..len - 1)
+        } else {
+            line
+        }
+    }
+
+    fn with_graphemes<T>(&self, line_idx: usize, f: impl FnOnce(&[usize]) -> T) -> T {
+        let mut cache = self.graphemes.borrow_mut();
+
+        if !matches!(*cache, Some((cached_idx, _)) if cached_idx == line_idx) {
+            let offsets = self
+                .line(line_idx)
+                .map(|line| line.grapheme_indices(true).map(|(i, _)| i).collect())
+                .unwrap_or_default();
+            *cache = Some((line_idx, offsets));
+        }
+
+        let (_, offsets) = cache.as_ref().unwrap();
+        f(offsets)
     }
 }
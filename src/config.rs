@@ -0,0 +1,36 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory holding the user's configuration: `$EDITRA_CONFIG_DIR`, or
+/// `editra` under `$XDG_CONFIG_HOME` (by default `~/.config`).
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("EDITRA_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => Path::new(&env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("editra"))
+}
+
+/// Files with the given extension in `subdir` of the configuration
+/// directory, sorted by name. Missing directories just give no files.
+pub fn config_files(subdir: &str, extension: &str) -> Vec<PathBuf> {
+    let Some(dir) = config_dir() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir.join(subdir)) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+    files
+}
@@ -1,31 +1,78 @@
 use std::{
-    env,
+    collections::HashMap,
+    env, fs,
     io::{self},
+    path::Path,
+    time::Duration,
 };
 
-use crossterm::event::{Event, KeyCode, KeyModifiers};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 
-use crate::{terminal::Terminal, view::View};
+use crate::{
+    highlighter::Languages,
+    prompt::{Prompt, PromptAction, PromptKind},
+    replace::ReplaceStage,
+    terminal::Terminal,
+    theme::Themes,
+    view::View,
+};
 
 pub struct Editor {
     quit: bool,
+    // Ctrl+Q was pressed once with unsaved changes
+    quit_pending: bool,
     pub view: View,
+    clipboard: String,
+    // mirror copies to the host clipboard with OSC 52
+    osc52: bool,
+    prompt: Option<Prompt>,
+    prompt_history: HashMap<PromptKind, Vec<String>>,
+    // target of a save waiting for the user to allow creating its directory
+    pending_save_path: Option<String>,
 }
 
-impl Editor {
-    pub fn default() -> Self {
+impl Default for Editor {
+    fn default() -> Self {
+        let mut view = View::default();
+        view.buffer.backup = env::var_os("EDITRA_BACKUP").is_some_and(|value| value != "0");
+        if let Some(margin) = env::var("EDITRA_SIDE_SCROLL_MARGIN")
+            .ok()
+            .and_then(|value| value.parse().ok())
+        {
+            view.side_scroll_margin = margin;
+        }
+
         Editor {
             quit: false,
-            view: View::default(),
+            quit_pending: false,
+            view,
+            clipboard: String::new(),
+            osc52: Self::osc52_enabled(),
+            prompt: None,
+            prompt_history: HashMap::new(),
+            pending_save_path: None,
         }
     }
+}
 
+impl Editor {
     pub fn run(&mut self) -> Result<(), io::Error> {
-        Terminal::initialize()?;
-        self.handle_args()?;
-        let result = self.render();
-        Terminal::terminate()?;
-        result
+        self.run_with_args(env::args())
+    }
+
+    /// Runs the editor as if started with the command line `args`, program
+    /// name included.
+    pub fn run_with_args(
+        &mut self,
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<(), io::Error> {
+        // the terminal is put back however the editor stops, and the first
+        // error wins
+        let result = Terminal::initialize()
+            .and_then(|()| self.handle_args(args))
+            .and_then(|()| self.render());
+        let terminated = Terminal::terminate();
+        result.and(terminated)
     }
 
     fn render(&mut self) -> Result<(), io::Error> {
@@ -35,7 +82,14 @@ impl Editor {
             if self.quit {
                 return Ok(());
             }
-            let event = crossterm::event::read()?;
+            // finish highlighting the file while the user is idle
+            while self.view.highlight_pending() && !Terminal::poll_event(Duration::ZERO)? {
+                self.view.highlight_idle();
+                if self.view.need_redraw {
+                    self.refresh_terminal()?;
+                }
+            }
+            let event = Terminal::read_event()?;
             self.resolve_event(&event)?;
         }
     }
@@ -46,7 +100,13 @@ impl Editor {
             Terminal::print("Goodbye")?;
         } else {
             self.view.render()?;
-            let (x, y) = self.view.get_cursor_location();
+            let (x, y) = match &self.prompt {
+                Some(prompt) => {
+                    prompt.render()?;
+                    (prompt.cursor_column(), Terminal::size().1 as usize - 1)
+                }
+                None => self.view.get_cursor_location(),
+            };
             Terminal::move_cursor_to(x as u16, y as u16)?;
         }
         Terminal::show_cursor()?;
@@ -55,24 +115,101 @@ impl Editor {
     }
 
     fn resolve_event(&mut self, event: &Event) -> Result<(), io::Error> {
+        if matches!(event, Event::Key(_)) && self.view.message.take().is_some() {
+            self.view.need_redraw = true;
+        }
+        let quit_pending = matches!(event, Event::Key(_)) && std::mem::take(&mut self.quit_pending);
+
         match event {
+            Event::Key(key_event) if self.prompt.is_some() => {
+                self.resolve_prompt_key(key_event);
+            }
+
+            Event::Key(key_event) if self.view.replace.stage == Some(ReplaceStage::Confirm) => {
+                self.resolve_replace_confirm_key(key_event);
+            }
+
             Event::Key(key_event) => match key_event.code {
                 KeyCode::Char('q') if key_event.modifiers == KeyModifiers::CONTROL => {
-                    self.quit = true;
+                    if self.view.buffer.modified && !quit_pending {
+                        self.quit_pending = true;
+                        self.view.message =
+                            Some("Unsaved changes! Press Ctrl+Q again to quit".to_owned());
+                        self.view.need_redraw = true;
+                    } else {
+                        self.quit = true;
+                    }
                 }
 
                 KeyCode::Char('s') if key_event.modifiers == KeyModifiers::CONTROL => {
-                    let old_file_type = self.view.buffer.file_type;
-                    self.view.buffer.save_buffer_as_file()?;
-                    if old_file_type != self.view.buffer.file_type {
-                        self.view
-                            .highlighter
-                            .update_file_type(self.view.buffer.file_type);
-                        self.view.highlighter.highlight_all(&self.view.buffer.lines);
-                        self.view.need_redraw = true;
+                    self.save();
+                }
+
+                KeyCode::Char('s') if key_event.modifiers == KeyModifiers::ALT => {
+                    self.open_save_as_prompt();
+                }
+
+                KeyCode::Char('t') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    self.open_prompt(
+                        PromptKind::FileType,
+                        "File type (empty to detect): ".to_owned(),
+                    );
+                }
+
+                KeyCode::Char('w') if key_event.modifiers == KeyModifiers::ALT => {
+                    self.view.toggle_soft_wrap();
+                }
+                KeyCode::Char('z') if key_event.modifiers == KeyModifiers::ALT => {
+                    Terminal::suspend()?;
+                    self.view.need_redraw = true;
+                }
+                KeyCode::Char('t') if key_event.modifiers == KeyModifiers::ALT => {
+                    self.open_prompt(PromptKind::Theme, "Theme: ".to_owned());
+                    if let Some(prompt) = self.prompt.as_mut() {
+                        prompt.hint = Some(format!("({})", Themes::get().names().join(", ")));
                     }
                 }
 
+                KeyCode::Char('z') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    self.view.undo()?;
+                }
+
+                KeyCode::Char('y') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    self.view.redo()?;
+                }
+
+                KeyCode::Char('c') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    if let Some(text) = self.view.selected_text() {
+                        self.copy_to_clipboard(text)?;
+                    }
+                }
+
+                KeyCode::Char('x') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    if let Some(text) = self.view.cut_selection() {
+                        self.copy_to_clipboard(text)?;
+                    }
+                }
+
+                KeyCode::Char('v') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    self.view.paste(&self.clipboard)?;
+                }
+
+                KeyCode::Char('f') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    self.view.start_search();
+                    self.open_prompt(PromptKind::Search, self.view.search.prompt_label());
+                }
+
+                KeyCode::Char('r') if key_event.modifiers == KeyModifiers::CONTROL => {
+                    self.view.start_replace();
+                    self.open_prompt(PromptKind::ReplacePattern, self.view.replace.prompt_label());
+                }
+
+                KeyCode::F(3) if key_event.modifiers.contains(KeyModifiers::SHIFT) => {
+                    self.view.search_previous();
+                }
+
+                KeyCode::F(3) => self.view.search_next(),
+
                 KeyCode::Up
                 | KeyCode::Down
                 | KeyCode::Left
@@ -80,7 +217,10 @@ impl Editor {
                 | KeyCode::PageDown
                 | KeyCode::PageUp
                 | KeyCode::End
-                | KeyCode::Home => self.view.update_cursor_location(key_event.code)?,
+                | KeyCode::Home => self.view.update_cursor_location(
+                    key_event.code,
+                    key_event.modifiers.contains(KeyModifiers::SHIFT),
+                )?,
                 KeyCode::Char(c) => self.view.insert_char_to_line(c)?,
                 KeyCode::Delete => self.view.delete_char()?,
                 KeyCode::Backspace => self.view.backspace_char()?,
@@ -90,6 +230,17 @@ impl Editor {
                 _ => {}
             },
 
+            Event::Paste(text) if self.prompt.is_some() => {
+                if let Some(prompt) = self.prompt.as_mut() {
+                    prompt.insert_str(text);
+                }
+                self.prompt_changed();
+            }
+
+            Event::Paste(text) => self.view.paste(text)?,
+
+            Event::Mouse(mouse_event) => self.view.handle_mouse(*mouse_event)?,
+
             Event::Resize(_w, _h) => {
                 self.view.need_redraw = true;
             }
@@ -100,11 +251,243 @@ impl Editor {
         Ok(())
     }
 
-    fn handle_args(&mut self) -> Result<(), io::Error> {
-        let args: Vec<String> = env::args().collect();
+    fn open_prompt(&mut self, kind: PromptKind, label: String) {
+        let history = self.prompt_history.get(&kind).cloned().unwrap_or_default();
+        self.prompt = Some(Prompt::new(kind, label, history));
+    }
+
+    fn close_prompt(&mut self) {
+        self.prompt = None;
+        self.view.need_redraw = true;
+    }
+
+    fn resolve_prompt_key(&mut self, key_event: &KeyEvent) {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return;
+        };
+        let kind = prompt.kind;
+        let alt = key_event.modifiers == KeyModifiers::ALT;
+        let control = key_event.modifiers == KeyModifiers::CONTROL;
+        let shift = key_event.modifiers.contains(KeyModifiers::SHIFT);
+
+        // keys that belong to the feature behind the prompt
+        match (kind, key_event.code) {
+            (PromptKind::Search, KeyCode::Char('c')) if alt => {
+                let found = self.view.toggle_search_case();
+                prompt.label = self.view.search.prompt_label();
+                return self.update_search_hint(found);
+            }
+            (PromptKind::Search, KeyCode::F(3)) if shift => {
+                return self.view.search_previous();
+            }
+            (PromptKind::Search, KeyCode::Char('p')) if control => {
+                return self.view.search_previous();
+            }
+            (PromptKind::Search, KeyCode::F(3)) => return self.view.search_next(),
+            (PromptKind::Search, KeyCode::Char('n')) if control => {
+                return self.view.search_next();
+            }
+            (PromptKind::CreateDirectory, KeyCode::Char('y' | 'Y')) => {
+                self.close_prompt();
+                return self.create_directory_and_save();
+            }
+            (PromptKind::CreateDirectory, KeyCode::Char('n' | 'N') | KeyCode::Esc) => {
+                self.close_prompt();
+                self.pending_save_path = None;
+                self.view.message = Some("Save cancelled".to_owned());
+                return;
+            }
+            (PromptKind::CreateDirectory, _) => return,
+            (PromptKind::ReplacePattern, KeyCode::Char('c')) if alt => {
+                self.view.replace.case_sensitive = !self.view.replace.case_sensitive;
+                prompt.label = self.view.replace.prompt_label();
+                return;
+            }
+            (PromptKind::ReplacePattern, KeyCode::Char('r')) if alt => {
+                self.view.replace.regex_mode = !self.view.replace.regex_mode;
+                prompt.label = self.view.replace.prompt_label();
+                return;
+            }
+            _ => {}
+        }
+
+        match prompt.handle_key(key_event) {
+            PromptAction::Changed => self.prompt_changed(),
+            PromptAction::Submit(input) => {
+                self.submit_prompt(kind, input, key_event.modifiers.contains(KeyModifiers::ALT))
+            }
+            PromptAction::Cancel => self.cancel_prompt(kind),
+            PromptAction::None => {}
+        }
+    }
+
+    fn prompt_changed(&mut self) {
+        let Some(prompt) = self.prompt.as_ref() else {
+            return;
+        };
+
+        if prompt.kind == PromptKind::Search {
+            self.view.search.query = prompt.input().to_owned();
+            let found = self.view.update_search();
+            self.update_search_hint(found);
+        }
+    }
+
+    fn update_search_hint(&mut self, found: bool) {
+        let no_match = !self.view.search.query.is_empty() && !found;
+        if let Some(prompt) = self.prompt.as_mut() {
+            prompt.hint = no_match.then(|| "(no match)".to_owned());
+        }
+    }
+
+    fn submit_prompt(&mut self, kind: PromptKind, input: String, alt: bool) {
+        self.remember_prompt_input(kind, &input);
+        self.close_prompt();
+
+        match kind {
+            PromptKind::Search => self.view.end_search(true),
+            PromptKind::ReplacePattern => {
+                self.view.replace.pattern = input;
+                self.view.submit_replace(false);
+                if self.view.replace.stage == Some(ReplaceStage::Replacement) {
+                    self.open_prompt(PromptKind::ReplaceWith, self.view.replace.prompt_label());
+                } else {
+                    self.view.finish_replace();
+                }
+            }
+            PromptKind::ReplaceWith => {
+                self.view.replace.replacement = input;
+                self.view.submit_replace(alt);
+            }
+            PromptKind::SaveAs => self.save_as(&input),
+            PromptKind::FileType => self.view.set_file_type(input.trim()),
+            PromptKind::Theme => self.view.set_theme(input.trim()),
+            PromptKind::CreateDirectory => {}
+        }
+    }
+
+    fn cancel_prompt(&mut self, kind: PromptKind) {
+        self.close_prompt();
+
+        match kind {
+            PromptKind::Search => self.view.end_search(false),
+            PromptKind::ReplacePattern | PromptKind::ReplaceWith => self.view.finish_replace(),
+            PromptKind::SaveAs | PromptKind::CreateDirectory => {
+                self.pending_save_path = None;
+                self.view.message = Some("Save cancelled".to_owned());
+            }
+            PromptKind::FileType | PromptKind::Theme => {}
+        }
+    }
+
+    fn remember_prompt_input(&mut self, kind: PromptKind, input: &str) {
+        if input.is_empty() {
+            return;
+        }
+
+        let history = self.prompt_history.entry(kind).or_default();
+        if history.last().is_none_or(|last| last != input) {
+            history.push(input.to_owned());
+        }
+    }
+
+    fn save(&mut self) {
+        if self.view.buffer.file_name.is_some() {
+            self.view.save(None);
+        } else {
+            self.open_save_as_prompt();
+        }
+    }
+
+    fn open_save_as_prompt(&mut self) {
+        self.open_prompt(PromptKind::SaveAs, "Save as: ".to_owned());
+        if let (Some(prompt), Some(name)) = (self.prompt.as_mut(), &self.view.buffer.file_name) {
+            prompt.set_input(name);
+        }
+    }
+
+    fn save_as(&mut self, path: &str) {
+        let path = Self::expand_home(path.trim());
+        if path.is_empty() {
+            self.view.message = Some("Save cancelled".to_owned());
+            return;
+        }
+
+        if let Some(parent) = Path::new(&path).parent()
+            && !parent.as_os_str().is_empty()
+            && !parent.is_dir()
+        {
+            let label = format!(
+                "Directory {} does not exist. Create it? (y/n) ",
+                parent.display()
+            );
+            self.open_prompt(PromptKind::CreateDirectory, label);
+            self.pending_save_path = Some(path);
+            return;
+        }
+
+        self.view.save(Some(path));
+    }
+
+    fn create_directory_and_save(&mut self) {
+        let Some(path) = self.pending_save_path.take() else {
+            return;
+        };
+
+        let parent = Path::new(&path).parent().unwrap_or(Path::new(""));
+        match fs::create_dir_all(parent) {
+            Ok(()) => self.view.save(Some(path)),
+            Err(err) => {
+                self.view.message = Some(format!("Could not create {}: {}", parent.display(), err));
+            }
+        }
+    }
+
+    // Expands a leading `~/` to the home directory.
+    fn expand_home(path: &str) -> String {
+        match (path.strip_prefix("~/"), env::var("HOME")) {
+            (Some(rest), Ok(home)) => Path::new(&home).join(rest).display().to_string(),
+            _ => path.to_owned(),
+        }
+    }
+
+    fn resolve_replace_confirm_key(&mut self, key_event: &KeyEvent) {
+        match key_event.code {
+            KeyCode::Char('y') | KeyCode::Enter => self.view.replace_current(),
+            KeyCode::Char('n') | KeyCode::Down => self.view.skip_replacement(),
+            KeyCode::Char('a') => self.view.replace_remaining(),
+            KeyCode::Char('q') | KeyCode::Esc => self.view.finish_replace(),
+            _ => {}
+        }
+    }
+
+    fn copy_to_clipboard(&mut self, text: String) -> Result<(), io::Error> {
+        if self.osc52 {
+            Terminal::copy_to_host_clipboard(&text)?;
+        }
+        self.clipboard = text;
+        Ok(())
+    }
+
+    // EDITRA_OSC52=1/0 forces it on or off, otherwise it is used over SSH,
+    // where the host clipboard is otherwise out of reach.
+    fn osc52_enabled() -> bool {
+        match env::var("EDITRA_OSC52") {
+            Ok(value) => value != "0",
+            Err(_) => env::var_os("SSH_TTY").is_some() || env::var_os("SSH_CONNECTION").is_some(),
+        }
+    }
+
+    fn handle_args(&mut self, args: impl IntoIterator<Item = String>) -> Result<(), io::Error> {
+        if let Some(path) = args.into_iter().nth(1) {
+            self.view.load(path)?;
+        }
 
-        if let Some(path) = args.get(1) {
-            self.view.load(path.to_owned())?;
+        if let Some(error) = Themes::get().errors.first() {
+            self.view.message = Some(format!("Skipped theme {}", error));
+        }
+        if let Some(error) = Languages::get().errors.first() {
+            self.view.message = Some(format!("Skipped language definition {}", error));
         }
 
         Ok(())
@@ -1,7 +1,30 @@
-use std::collections::HashMap;
-use unicode_segmentation::UnicodeSegmentation;
+use std::ops::Range;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use serde::Deserialize;
+
+use crate::buffer::{Buffer, LineChange};
+
+mod generic;
+mod json;
+mod language;
+mod lexer;
+mod markdown;
+mod rust;
+mod toml;
+mod yaml;
+
+use self::toml::TomlSyntaxHighlighter;
+use generic::GenericHighlighter;
+use json::JsonSyntaxHighlighter;
+pub use language::{LanguageDefinition, Languages};
+use markdown::MarkdownSyntaxHighlighter;
+use rust::RustSyntaxHighlighter;
+use yaml::YamlSyntaxHighlighter;
+
+/// Category of highlighted text. Themes pick its style by the snake_case
+/// name, like `doc_comment`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum HighlightType {
     None,
     Number,
@@ -11,32 +34,16 @@ pub enum HighlightType {
     Character,
     Lifetime,
     Comment,
+    DocComment,
     String,
-}
-
-impl HighlightType {
-    pub fn to_color(&self) -> crossterm::style::Color {
-        use crossterm::style::Color;
-        match self {
-            HighlightType::Number => Color::Red,
-            HighlightType::Keyword => Color::Blue,
-            HighlightType::Type => Color::Green,
-            HighlightType::Literal => Color::Magenta,
-            HighlightType::Character => Color::Rgb {
-                r: 255,
-                g: 191,
-                b: 0,
-            },
-            HighlightType::Lifetime => Color::Cyan,
-            HighlightType::Comment => Color::DarkGreen,
-            HighlightType::String => Color::Rgb {
-                r: 255,
-                g: 165,
-                b: 0,
-            },
-            HighlightType::None => Color::Reset,
-        }
-    }
+    Attribute,
+    Macro,
+    /// Keys of TOML, JSON and YAML.
+    Key,
+    /// Markdown headings and TOML table headers.
+    Heading,
+    Emphasis,
+    Error,
 }
 
 #[derive(Debug, Clone)]
@@ -46,259 +53,302 @@ pub struct Annotation {
     pub highlight_type: HighlightType,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum FileType {
-    PlainText,
-    Rust,
-}
+// lines at either end of a file searched for a modeline
+const MODELINE_LINES: usize = 5;
+
+/// A language known to the editor, as an index into the loaded language
+/// definitions. The default is plain text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileType(usize);
 
 impl FileType {
     pub fn from_filename(filename: &str) -> Self {
-        if filename.ends_with(".rs") {
-            FileType::Rust
-        } else {
-            FileType::PlainText
-        }
+        FileType(Languages::get().find_by_filename(filename).unwrap_or(0))
     }
 
-    pub fn name(&self) -> &str {
-        match self {
-            FileType::PlainText => "Text",
-            FileType::Rust => "Rust",
-        }
+    /// The language called `name`, by its name, an alias or an extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Languages::get().find_by_name(name).map(FileType)
     }
-}
-
-trait SyntaxHighlighter {
-    fn highlight(&self, line: &str) -> Vec<Annotation>;
-}
 
-struct PlainTextHighlighter;
+    /// File type of `buffer` saved as `filename`: the one set by a modeline
+    /// in its first or last lines, else the one its name suggests, else
+    /// the interpreter on its `#!` line.
+    pub fn detect(filename: &str, buffer: &Buffer) -> Self {
+        let languages = Languages::get();
+        let line_count = buffer.line_count();
+        let tail_start = line_count
+            .saturating_sub(MODELINE_LINES)
+            .max(MODELINE_LINES);
 
-impl SyntaxHighlighter for PlainTextHighlighter {
-    fn highlight(&self, _line: &str) -> Vec<Annotation> {
-        Vec::new()
+        let found = buffer
+            .lines_at(0)
+            .take(MODELINE_LINES)
+            .chain(buffer.lines_at(tail_start.min(line_count)))
+            .find_map(|line| languages.find_by_modeline(&line))
+            .or_else(|| languages.find_by_filename(filename))
+            .or_else(|| {
+                buffer
+                    .line(0)
+                    .and_then(|line| languages.find_by_shebang(&line))
+            });
+        FileType(found.unwrap_or(0))
     }
-}
 
-impl Default for PlainTextHighlighter {
-    fn default() -> Self {
-        Self
+    pub fn name(&self) -> &'static str {
+        &self.definition().name
     }
-}
 
-struct RustSyntaxHighlighter {
-    keywords: Vec<&'static str>,
-    types: Vec<&'static str>,
-    literals: Vec<&'static str>,
-}
-
-impl RustSyntaxHighlighter {
-    fn new() -> Self {
-        Self {
-            keywords: vec![
-                "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else",
-                "enum", "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
-                "move", "mut", "pub", "ref", "return", "self", "Self", "struct", "super", "trait",
-                "type", "unsafe", "use", "where", "while",
-            ],
-            types: vec![
-                "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128",
-                "usize", "f32", "f64", "bool", "char", "String", "str", "Vec", "HashMap", "Option",
-                "Result",
-            ],
-            literals: vec!["Some", "None", "Ok", "Err", "true", "false"],
-        }
-    }
-
-    fn is_number(word: &str) -> bool {
-        if word.is_empty() {
-            return false;
-        }
-
-        // Hex, binary, octal
-        if word.len() >= 3 {
-            let prefix = &word[..2];
-            if (prefix == "0x" || prefix == "0X")
-                && word[2..].chars().all(|c| c.is_ascii_hexdigit() || c == '_')
-                && !word.starts_with("0x_")
-                && !word.ends_with('_')
-            {
-                return true;
-            }
-            if (prefix == "0b" || prefix == "0B")
-                && word[2..].chars().all(|c| c == '0' || c == '1' || c == '_')
-                && !word.starts_with("0b_")
-                && !word.ends_with('_')
-            {
-                return true;
-            }
-            if (prefix == "0o" || prefix == "0O")
-                && word[2..]
-                    .chars()
-                    .all(|c| ('0'..='7').contains(&c) || c == '_')
-                && !word.starts_with("0o_")
-                && !word.ends_with('_')
-            {
-                return true;
-            }
-        }
-
-        // Regular numbers (int, float, scientific)
-        let mut has_dot = false;
-        let mut has_e = false;
-        let mut after_e = false;
-
-        for (i, c) in word.chars().enumerate() {
-            if c == '.' {
-                if has_dot || after_e || i == 0 || i == word.len() - 1 {
-                    return false;
-                }
-                has_dot = true;
-            } else if c == 'e' || c == 'E' {
-                if has_e || i == 0 || i == word.len() - 1 {
-                    return false;
-                }
-                has_e = true;
-                after_e = true;
-            } else if c == '_' {
-                if i == 0 || i == word.len() - 1 {
-                    return false;
-                }
-            } else if !c.is_ascii_digit() {
-                return false;
-            }
-
-            if after_e && c != 'e' && c != 'E' && c == '.' {
-                return false;
-            }
-        }
-
-        true
+    fn definition(&self) -> &'static LanguageDefinition {
+        &Languages::get().definitions[self.0]
     }
 }
 
-impl SyntaxHighlighter for RustSyntaxHighlighter {
-    fn highlight(&self, line: &str) -> Vec<Annotation> {
-        let mut annotations = Vec::new();
-        let _byte_index = 0;
-
-        // Check for comments first
-        if let Some(pos) = line.find("//") {
-            annotations.push(Annotation {
-                start: pos,
-                end: line.len(),
-                highlight_type: HighlightType::Comment,
-            });
-            return annotations;
-        }
-
-        // Simple word-based highlighting
-        for (start, word) in line.split_word_bound_indices() {
-            if word.chars().all(|c| c.is_whitespace()) {
-                continue;
-            }
-
-            if self.keywords.contains(&word) {
-                annotations.push(Annotation {
-                    start,
-                    end: start + word.len(),
-                    highlight_type: HighlightType::Keyword,
-                });
-            } else if self.types.contains(&word) {
-                annotations.push(Annotation {
-                    start,
-                    end: start + word.len(),
-                    highlight_type: HighlightType::Type,
-                });
-            } else if self.literals.contains(&word) {
-                annotations.push(Annotation {
-                    start,
-                    end: start + word.len(),
-                    highlight_type: HighlightType::Literal,
-                });
-            } else if Self::is_number(word) {
-                annotations.push(Annotation {
-                    start,
-                    end: start + word.len(),
-                    highlight_type: HighlightType::Number,
-                });
-            } else if word.starts_with('"') {
-                // Simple string highlighting
-                if let Some(end_pos) = line[start + 1..].find('"') {
-                    annotations.push(Annotation {
-                        start,
-                        end: start + end_pos + 2,
-                        highlight_type: HighlightType::String,
-                    });
-                }
-            } else if word.starts_with('\'') && word.len() >= 2 {
-                // Simple char highlighting
-                annotations.push(Annotation {
-                    start,
-                    end: start + word.len().min(3),
-                    highlight_type: HighlightType::Character,
-                });
-            }
-        }
+/// Where a line leaves the lexer, so constructs spanning several lines
+/// (block comments, multi-line strings) carry over to the next one.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum LexState {
+    #[default]
+    Normal,
+    /// Inside a block comment, nested `depth` levels deep.
+    BlockComment { depth: usize, doc: bool },
+    /// Inside a string literal of the given kind, as numbered by the
+    /// highlighter.
+    String(usize),
+    /// Inside a raw string closed by `"` followed by this many `#`.
+    RawString(usize),
+    /// Inside an attribute, this many brackets deep.
+    Attribute(usize),
+    /// After a `,` that only whitespace has followed so far, where a
+    /// closing bracket would make it a trailing comma.
+    Comma,
+    /// Inside a YAML block scalar under a line indented this much.
+    BlockScalar(usize),
+    /// Inside a Markdown code block fenced by `len` backticks or tildes,
+    /// with the state of the code's language, if it is a known one.
+    Fenced {
+        tildes: bool,
+        len: usize,
+        language: Option<FileType>,
+        inner: Box<LexState>,
+    },
+}
 
-        annotations
-    }
+trait SyntaxHighlighter {
+    /// Annotations for `line` when the previous line ended in `state`, and
+    /// the state this line ends in.
+    fn highlight(&self, line: &str, state: LexState) -> (Vec<Annotation>, LexState);
 }
 
-impl Default for RustSyntaxHighlighter {
-    fn default() -> Self {
-        Self::new()
-    }
+#[derive(Default)]
+struct HighlightedLine {
+    annotations: Vec<Annotation>,
+    end_state: LexState,
 }
 
 pub struct Highlighter {
     syntax_highlighter: Box<dyn SyntaxHighlighter>,
-    annotations: HashMap<usize, Vec<Annotation>>,
+    // entries for the lines highlighted so far, always a prefix of the
+    // buffer since each line's state depends on the ones before it
+    lines: Vec<HighlightedLine>,
+    // lines changed since the last refresh
+    dirty: Option<Range<usize>>,
+    // lines far past the prefix, highlighted on their own so they can be
+    // shown right away, starting from line `provisional_start`
+    provisional: Vec<HighlightedLine>,
+    provisional_start: usize,
 }
 
+// How far past the highlighted prefix the lines to show may be for the
+// prefix to be extended to them, rather than highlighting them provisionally.
+const MAX_CATCH_UP_LINES: usize = 1000;
+
 impl Highlighter {
     pub fn new(file_type: FileType) -> Self {
         Self {
             syntax_highlighter: Self::create_syntax_highlighter(file_type),
-            annotations: HashMap::new(),
+            lines: Vec::new(),
+            dirty: None,
+            provisional: Vec::new(),
+            provisional_start: 0,
         }
     }
 
     fn create_syntax_highlighter(file_type: FileType) -> Box<dyn SyntaxHighlighter> {
-        match file_type {
-            FileType::Rust => Box::<RustSyntaxHighlighter>::default(),
-            FileType::PlainText => Box::<PlainTextHighlighter>::default(),
+        let definition = file_type.definition();
+        match definition.lexer.as_deref() {
+            Some("rust") => Box::new(RustSyntaxHighlighter::new(definition)),
+            Some("toml") => Box::new(TomlSyntaxHighlighter::new(definition)),
+            Some("json") => Box::new(JsonSyntaxHighlighter::new(definition)),
+            Some("yaml") => Box::new(YamlSyntaxHighlighter::new(definition)),
+            Some("markdown") => Box::new(MarkdownSyntaxHighlighter),
+            _ => Box::new(GenericHighlighter::new(definition)),
         }
     }
 
     pub fn update_file_type(&mut self, file_type: FileType) {
         self.syntax_highlighter = Self::create_syntax_highlighter(file_type);
-        self.annotations.clear();
+        self.lines.clear();
+        self.dirty = None;
+        self.provisional.clear();
+    }
+
+    pub fn get_annotations(&self, line_idx: usize) -> Option<&Vec<Annotation>> {
+        let line = match self.lines.get(line_idx) {
+            Some(line) => Some(line),
+            None => line_idx
+                .checked_sub(self.provisional_start)
+                .and_then(|i| self.provisional.get(i)),
+        };
+        line.map(|line| &line.annotations)
+            .filter(|annotations| !annotations.is_empty())
     }
 
-    pub fn highlight_all(&mut self, lines: &[String]) {
-        self.annotations.clear();
-        for (line_idx, line) in lines.iter().enumerate() {
-            let annotations = self.syntax_highlighter.highlight(line);
-            if !annotations.is_empty() {
-                self.annotations.insert(line_idx, annotations);
+    /// Number of lines from the top of the buffer that are highlighted.
+    pub fn highlighted_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Makes the annotations of the lines in `range` available. Lines close
+    /// to the highlighted prefix are added to it, while lines far past it are
+    /// highlighted provisionally, as if nothing before them was left open,
+    /// until `extend_to` reaches them and corrects them.
+    pub fn highlight_range(&mut self, range: Range<usize>, buffer: &Buffer) {
+        if range.start <= self.lines.len() + MAX_CATCH_UP_LINES {
+            self.extend_to(range.end, buffer);
+            return;
+        }
+
+        let provisional_end = self.provisional_start + self.provisional.len();
+        // carry on from the provisional lines when the range follows them
+        let mut state = match self.provisional.last() {
+            Some(line) if (self.provisional_start..=provisional_end).contains(&range.start) => {
+                line.end_state.clone()
             }
+            _ => {
+                self.provisional.clear();
+                self.provisional_start = range.start;
+                LexState::Normal
+            }
+        };
+
+        let start = self.provisional_start + self.provisional.len();
+        for line in buffer.lines_at(start).take(range.end.saturating_sub(start)) {
+            let (annotations, end_state) = self.syntax_highlighter.highlight(&line, state);
+            self.provisional.push(HighlightedLine {
+                annotations,
+                end_state: end_state.clone(),
+            });
+            state = end_state;
         }
     }
 
-    pub fn get_annotations(&self, line_idx: usize) -> Option<&Vec<Annotation>> {
-        self.annotations.get(&line_idx)
+    /// Highlights lines past the highlighted prefix until it covers
+    /// `line_count` lines or reaches the end of the buffer. Returns whether
+    /// it reached lines that were highlighted provisionally, which then show
+    /// up differently.
+    pub fn extend_to(&mut self, line_count: usize, buffer: &Buffer) -> bool {
+        let start = self.lines.len();
+        let mut state = self
+            .lines
+            .last()
+            .map_or(LexState::Normal, |l| l.end_state.clone());
+
+        for line in buffer
+            .lines_at(start)
+            .take(line_count.saturating_sub(start))
+        {
+            let (annotations, end_state) = self.syntax_highlighter.highlight(&line, state);
+            self.lines.push(HighlightedLine {
+                annotations,
+                end_state: end_state.clone(),
+            });
+            state = end_state;
+        }
+
+        if self.provisional.is_empty() || self.lines.len() <= self.provisional_start {
+            return false;
+        }
+        if self.lines.len() >= self.provisional_start + self.provisional.len() {
+            self.provisional.clear();
+        }
+        true
     }
 
-    pub fn invalidate_from(&mut self, start_line: usize, lines: &[String]) {
-        for line_idx in start_line..lines.len() {
-            let annotations = self.syntax_highlighter.highlight(&lines[line_idx]);
-            if !annotations.is_empty() {
-                self.annotations.insert(line_idx, annotations);
-            } else {
-                self.annotations.remove(&line_idx);
+    /// Shifts the cached lines to follow a change to the buffer and marks
+    /// the lines it touched for re-highlighting by `refresh`. Changes past
+    /// the highlighted prefix need nothing, `extend_to` gets there later.
+    pub fn apply_change(&mut self, change: LineChange) {
+        // highlighted again for the next render
+        self.provisional.clear();
+        let touched = match change {
+            LineChange::Modified(line) if line >= self.lines.len() => return,
+            LineChange::Inserted { at, .. } if at > self.lines.len() => return,
+            LineChange::Modified(line) => line..line + 1,
+            LineChange::Inserted { at, count } => {
+                let at = at.min(self.lines.len());
+                self.lines
+                    .splice(at..at, (0..count).map(|_| HighlightedLine::default()));
+                let shift = |line: usize| if line >= at { line + count } else { line };
+                self.dirty = self
+                    .dirty
+                    .take()
+                    .map(|dirty| shift(dirty.start)..shift(dirty.end));
+                at..at + count
+            }
+            LineChange::Removed { at, count } => {
+                let end = (at + count).min(self.lines.len());
+                self.lines.drain(at.min(end)..end);
+                let shift = |line: usize| match line {
+                    _ if line >= at + count => line - count,
+                    _ if line > at => at,
+                    _ => line,
+                };
+                self.dirty = self
+                    .dirty
+                    .take()
+                    .map(|dirty| shift(dirty.start)..shift(dirty.end));
+                return;
+            }
+        };
+
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(touched.start)..dirty.end.max(touched.end),
+            None => touched,
+        });
+    }
+
+    /// Re-highlights the lines marked by `apply_change`. Lines past them are
+    /// only revisited while the lexer state they start in keeps changing.
+    pub fn refresh(&mut self, buffer: &Buffer) {
+        let Some(dirty) = self.dirty.take() else {
+            return;
+        };
+        let line_count = buffer.line_count().min(self.lines.len());
+        let start = dirty.start.min(line_count);
+        let end = dirty.end.clamp(start, line_count);
+
+        let mut state = match start {
+            0 => LexState::Normal,
+            _ => self.lines[start - 1].end_state.clone(),
+        };
+        self.lines.truncate(line_count);
+        // lines past the prefix are picked up by `extend_to`
+        for (line_idx, line) in buffer.lines_at(start).take(line_count - start).enumerate() {
+            let line_idx = start + line_idx;
+            let (annotations, end_state) = self.syntax_highlighter.highlight(&line, state);
+            let old = std::mem::replace(
+                &mut self.lines[line_idx],
+                HighlightedLine {
+                    annotations,
+                    end_state: end_state.clone(),
+                },
+            );
+
+            if line_idx >= end && old.end_state == end_state {
+                break;
             }
+            state = end_state;
         }
     }
 }
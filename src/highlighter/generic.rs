@@ -0,0 +1,147 @@
+use super::lexer::{Lexer, number_end};
+use super::{Annotation, HighlightType, LanguageDefinition, LexState, SyntaxHighlighter};
+
+/// Highlighter driven entirely by the rules of a language definition.
+pub(super) struct GenericHighlighter {
+    definition: &'static LanguageDefinition,
+}
+
+impl GenericHighlighter {
+    pub fn new(definition: &'static LanguageDefinition) -> Self {
+        Self { definition }
+    }
+
+    fn next_token(&self, lexer: &mut Lexer) -> LexState {
+        let definition = self.definition;
+        let start = lexer.pos;
+        let rest = lexer.rest();
+        let Some(c) = rest.chars().next() else {
+            return LexState::Normal;
+        };
+
+        // block comments first, as their opener may begin with a line
+        // comment's, like Lua's `--[[`
+        if let Some((open, _)) = &definition.block_comment
+            && rest.starts_with(open.as_str())
+        {
+            lexer.pos += open.len();
+            let doc = self.comment_type(rest) == HighlightType::DocComment;
+            return self.finish(lexer, start, LexState::BlockComment { depth: 1, doc });
+        }
+        let word_start = !definition.comments_start_words
+            || lexer.line[..start]
+                .chars()
+                .next_back()
+                .is_none_or(|c| c.is_whitespace() || ";&|()<>".contains(c));
+        if word_start
+            && definition
+                .line_comments
+                .iter()
+                .any(|prefix| rest.starts_with(prefix.as_str()))
+        {
+            lexer.eat_rest();
+            lexer.push(start, self.comment_type(rest));
+            return LexState::Normal;
+        }
+
+        let string = definition
+            .strings
+            .iter()
+            .enumerate()
+            .find_map(|(kind, rule)| Some((kind, rule.opening_len(rest)?)));
+        if let Some((kind, len)) = string {
+            lexer.pos += len;
+            return self.finish(lexer, start, LexState::String(kind));
+        }
+
+        if let Some(rule) = &definition.numbers
+            && c.is_ascii_digit()
+        {
+            lexer.pos = number_end(lexer.line, start, rule);
+            lexer.push(start, HighlightType::Number);
+        } else if definition.is_word_char(c) {
+            lexer.eat_while(|c| definition.is_word_char(c));
+            if let Some(highlight_type) = definition.word_type(&lexer.line[start..lexer.pos]) {
+                lexer.push(start, highlight_type);
+            }
+        } else {
+            lexer.pos += c.len_utf8();
+        }
+
+        LexState::Normal
+    }
+
+    // Lexes the rest of the construct `state` describes, which began at
+    // `start`, up to its end or the end of the line.
+    fn finish(&self, lexer: &mut Lexer, start: usize, state: LexState) -> LexState {
+        let definition = self.definition;
+
+        let (next, highlight_type) = match state {
+            LexState::BlockComment { depth, doc } => {
+                let Some((open, close)) = &definition.block_comment else {
+                    return LexState::Normal;
+                };
+                let depth = lexer.block_comment(open, close, definition.nested_comments, depth);
+                let next = match depth {
+                    0 => LexState::Normal,
+                    _ => LexState::BlockComment { depth, doc },
+                };
+                let highlight_type = if doc {
+                    HighlightType::DocComment
+                } else {
+                    HighlightType::Comment
+                };
+                (next, highlight_type)
+            }
+            LexState::String(kind) => {
+                let Some(rule) = definition.strings.get(kind) else {
+                    return LexState::Normal;
+                };
+                let end = rule.end.as_ref().unwrap_or(&rule.delimiter);
+                let closed = lexer.string(end, !rule.raw);
+                // an unterminated single-line string just ends with the line
+                let next = if closed || !rule.multiline {
+                    LexState::Normal
+                } else {
+                    state
+                };
+                let highlight_type = if rule.character {
+                    HighlightType::Character
+                } else {
+                    HighlightType::String
+                };
+                (next, highlight_type)
+            }
+            _ => return LexState::Normal,
+        };
+
+        lexer.push(start, highlight_type);
+        next
+    }
+
+    fn comment_type(&self, comment: &str) -> HighlightType {
+        let doc = self
+            .definition
+            .doc_comments
+            .iter()
+            .any(|prefix| comment.starts_with(prefix.as_str()));
+        if doc {
+            HighlightType::DocComment
+        } else {
+            HighlightType::Comment
+        }
+    }
+}
+
+impl SyntaxHighlighter for GenericHighlighter {
+    fn highlight(&self, line: &str, state: LexState) -> (Vec<Annotation>, LexState) {
+        let mut lexer = Lexer::new(line);
+
+        let mut state = self.finish(&mut lexer, 0, state);
+        while state == LexState::Normal && !lexer.is_done() {
+            state = self.next_token(&mut lexer);
+        }
+
+        (lexer.annotations, state)
+    }
+}
@@ -0,0 +1,85 @@
+use super::lexer::{Lexer, number_end};
+use super::{Annotation, HighlightType, LanguageDefinition, LexState, SyntaxHighlighter};
+
+/// Tokenizer for JSON, telling keys from values and flagging trailing
+/// commas, which JSON does not allow.
+pub(super) struct JsonSyntaxHighlighter {
+    definition: &'static LanguageDefinition,
+}
+
+impl JsonSyntaxHighlighter {
+    pub fn new(definition: &'static LanguageDefinition) -> Self {
+        Self { definition }
+    }
+}
+
+impl SyntaxHighlighter for JsonSyntaxHighlighter {
+    fn highlight(&self, line: &str, state: LexState) -> (Vec<Annotation>, LexState) {
+        let mut lexer = Lexer::new(line);
+        // a comma not yet followed by anything, with its position when it
+        // is on this line
+        let mut comma = (state == LexState::Comma).then_some(None);
+
+        loop {
+            lexer.eat_while(char::is_whitespace);
+            let start = lexer.pos;
+            let rest = lexer.rest();
+            let Some(c) = rest.chars().next() else {
+                break;
+            };
+
+            match c {
+                ',' => {
+                    lexer.pos += 1;
+                    comma = Some(Some(start));
+                    continue;
+                }
+                ']' | '}' if comma.is_some() => {
+                    // flag the comma, or the bracket when the comma is on
+                    // an earlier line
+                    let at = comma.flatten().unwrap_or(start);
+                    lexer.annotations.push(Annotation {
+                        start: at,
+                        end: at + 1,
+                        highlight_type: HighlightType::Error,
+                    });
+                    lexer.pos += 1;
+                }
+                '"' => {
+                    lexer.pos += 1;
+                    lexer.string("\"", true);
+                    let highlight_type = if lexer.rest().trim_start().starts_with(':') {
+                        HighlightType::Key
+                    } else {
+                        HighlightType::String
+                    };
+                    lexer.push(start, highlight_type);
+                }
+                '-' | '0'..='9' => {
+                    let digits = start + usize::from(c == '-');
+                    lexer.pos = match &self.definition.numbers {
+                        Some(rule) => number_end(line, digits, rule),
+                        None => digits + 1,
+                    };
+                    lexer.push(start, HighlightType::Number);
+                }
+                _ if c.is_alphabetic() => {
+                    lexer.eat_while(char::is_alphanumeric);
+                    if let Some(highlight_type) = self.definition.word_type(&line[start..lexer.pos])
+                    {
+                        lexer.push(start, highlight_type);
+                    }
+                }
+                _ => lexer.pos += c.len_utf8(),
+            }
+            comma = None;
+        }
+
+        let state = if comma.is_some() {
+            LexState::Comma
+        } else {
+            LexState::Normal
+        };
+        (lexer.annotations, state)
+    }
+}
@@ -0,0 +1,336 @@
+use std::{collections::HashMap, fs, sync::OnceLock};
+
+use serde::Deserialize;
+
+use super::HighlightType;
+use crate::config;
+
+// Built-in definitions, in the same format as user files. Plain text must
+// come first: it is the default file type.
+const BUILTIN: &[(&str, &str)] = &[
+    ("text.toml", include_str!("../../languages/text.toml")),
+    ("rust.toml", include_str!("../../languages/rust.toml")),
+    ("python.toml", include_str!("../../languages/python.toml")),
+    (
+        "javascript.toml",
+        include_str!("../../languages/javascript.toml"),
+    ),
+    (
+        "typescript.toml",
+        include_str!("../../languages/typescript.toml"),
+    ),
+    ("c.toml", include_str!("../../languages/c.toml")),
+    ("cpp.toml", include_str!("../../languages/cpp.toml")),
+    ("go.toml", include_str!("../../languages/go.toml")),
+    ("shell.toml", include_str!("../../languages/shell.toml")),
+    ("toml.toml", include_str!("../../languages/toml.toml")),
+    ("json.toml", include_str!("../../languages/json.toml")),
+    ("yaml.toml", include_str!("../../languages/yaml.toml")),
+    (
+        "markdown.toml",
+        include_str!("../../languages/markdown.toml"),
+    ),
+    (
+        "makefile.toml",
+        include_str!("../../languages/makefile.toml"),
+    ),
+    (
+        "dockerfile.toml",
+        include_str!("../../languages/dockerfile.toml"),
+    ),
+];
+
+// Tokenizers a definition can pick with `lexer = "..."`.
+const LEXERS: &[&str] = &["rust", "toml", "json", "yaml", "markdown"];
+
+/// A language as described by a definition file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageDefinition {
+    pub name: String,
+    /// Other names for the language in modelines and code fences, like
+    /// `make`.
+    pub aliases: Vec<String>,
+    /// File name extensions, without the leading dot.
+    pub extensions: Vec<String>,
+    /// Whole file names, like `Makefile`. These win over extensions.
+    pub filenames: Vec<String>,
+    /// Interpreters that select this language from a `#!` line, like
+    /// `python3`. Version numbers after the name are ignored.
+    pub shebangs: Vec<String>,
+    /// Built-in tokenizer to use instead of the generic rules below.
+    pub lexer: Option<String>,
+    pub keywords: Vec<String>,
+    pub types: Vec<String>,
+    pub literals: Vec<String>,
+    /// Characters besides letters, digits and `_` that may appear in words.
+    pub word_chars: String,
+    pub line_comments: Vec<String>,
+    /// Line comments only start a word, as in shell where `$#` is not a
+    /// comment.
+    pub comments_start_words: bool,
+    /// Opening and closing delimiters of block comments.
+    pub block_comment: Option<(String, String)>,
+    pub nested_comments: bool,
+    /// Comment openers that mark documentation, like `///`.
+    pub doc_comments: Vec<String>,
+    /// String and char literals, tried in order, so longer delimiters
+    /// should come first.
+    pub strings: Vec<StringRule>,
+    pub numbers: Option<NumberRule>,
+    #[serde(skip)]
+    words: HashMap<String, HighlightType>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StringRule {
+    pub delimiter: String,
+    /// Letters that may come right before the delimiter, like Python's `r`
+    /// or C's `u8`. Matched ignoring case.
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    /// Closing delimiter, when it differs from the opening one.
+    #[serde(default)]
+    pub end: Option<String>,
+    /// The literal may continue on the following lines.
+    #[serde(default)]
+    pub multiline: bool,
+    /// Backslash escapes are not recognized.
+    #[serde(default)]
+    pub raw: bool,
+    /// Highlight as a character literal rather than a string.
+    #[serde(default)]
+    pub character: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NumberRule {
+    /// Radix prefixes such as `0x`, `0o` and `0b`.
+    pub prefixes: Vec<String>,
+    /// `_` may separate digits.
+    pub underscores: bool,
+    /// Letters may follow a number as a type suffix, like `10u8`.
+    pub suffixes: bool,
+}
+
+impl StringRule {
+    /// Length of the opening of this literal if `text` starts with one.
+    pub fn opening_len(&self, text: &str) -> Option<usize> {
+        if text.starts_with(self.delimiter.as_str()) {
+            return Some(self.delimiter.len());
+        }
+
+        self.prefixes.iter().find_map(|prefix| {
+            let rest = text.get(prefix.len()..)?;
+            (text[..prefix.len()].eq_ignore_ascii_case(prefix)
+                && rest.starts_with(self.delimiter.as_str()))
+            .then_some(prefix.len() + self.delimiter.len())
+        })
+    }
+}
+
+impl LanguageDefinition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut definition: LanguageDefinition =
+            toml::from_str(source).map_err(|err| err.message().to_owned())?;
+
+        if definition.name.is_empty() {
+            return Err("missing `name`".to_owned());
+        }
+        if let Some(lexer) = &definition.lexer
+            && !LEXERS.contains(&lexer.as_str())
+        {
+            return Err(format!("unknown lexer `{}`", lexer));
+        }
+        if definition
+            .strings
+            .iter()
+            .any(|rule| rule.delimiter.is_empty())
+            || definition
+                .block_comment
+                .as_ref()
+                .is_some_and(|(open, close)| open.is_empty() || close.is_empty())
+            || definition.line_comments.iter().any(String::is_empty)
+        {
+            return Err("empty delimiter".to_owned());
+        }
+
+        let categories = [
+            (&definition.keywords, HighlightType::Keyword),
+            (&definition.types, HighlightType::Type),
+            (&definition.literals, HighlightType::Literal),
+        ];
+        let mut words = HashMap::new();
+        for (list, highlight_type) in categories {
+            for word in list {
+                words.entry(word.clone()).or_insert(highlight_type);
+            }
+        }
+        definition.words = words;
+
+        Ok(definition)
+    }
+
+    /// Category of a keyword, type or literal.
+    pub fn word_type(&self, word: &str) -> Option<HighlightType> {
+        self.words.get(word).copied()
+    }
+
+    pub fn is_word_char(&self, c: char) -> bool {
+        c == '_' || c.is_alphanumeric() || self.word_chars.contains(c)
+    }
+
+    fn matches_extension(&self, filename: &str) -> bool {
+        self.extensions.iter().any(|ext| {
+            filename
+                .strip_suffix(ext.as_str())
+                .is_some_and(|stem| stem.ends_with('.'))
+        })
+    }
+
+    fn matches_interpreter(&self, interpreter: &str) -> bool {
+        self.shebangs.iter().any(|name| {
+            interpreter
+                .strip_prefix(name.as_str())
+                .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        })
+    }
+}
+
+/// All known languages: the built-in ones, replaced or extended by the
+/// `*.toml` files in the `languages` configuration directory.
+pub struct Languages {
+    pub definitions: Vec<LanguageDefinition>,
+    /// Definition files that could not be loaded, with the reason.
+    pub errors: Vec<String>,
+}
+
+static LANGUAGES: OnceLock<Languages> = OnceLock::new();
+
+impl Languages {
+    pub fn get() -> &'static Languages {
+        LANGUAGES.get_or_init(Self::load)
+    }
+
+    fn load() -> Self {
+        let mut languages = Languages {
+            definitions: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for (origin, source) in BUILTIN {
+            languages.add(origin, source);
+        }
+        for path in config::config_files("languages", "toml") {
+            match fs::read_to_string(&path) {
+                Ok(source) => languages.add(&path.display().to_string(), &source),
+                Err(err) => languages
+                    .errors
+                    .push(format!("{}: {}", path.display(), err)),
+            }
+        }
+
+        languages
+    }
+
+    // A definition with the name of a known language replaces it.
+    fn add(&mut self, origin: &str, source: &str) {
+        let definition = match LanguageDefinition::parse(source) {
+            Ok(definition) => definition,
+            Err(err) => return self.errors.push(format!("{}: {}", origin, err)),
+        };
+
+        let existing = self
+            .definitions
+            .iter_mut()
+            .find(|known| known.name.eq_ignore_ascii_case(&definition.name));
+        match existing {
+            Some(known) => *known = definition,
+            None => self.definitions.push(definition),
+        }
+    }
+
+    pub fn find_by_filename(&self, filename: &str) -> Option<usize> {
+        let name = filename.rsplit('/').next().unwrap_or(filename);
+        self.definitions
+            .iter()
+            .position(|definition| definition.filenames.iter().any(|known| known == name))
+            .or_else(|| {
+                self.definitions
+                    .iter()
+                    .position(|definition| definition.matches_extension(filename))
+            })
+    }
+
+    /// Language called `name`, by its name, an alias or an extension, as
+    /// in a modeline or the info string of a Markdown code fence.
+    pub fn find_by_name(&self, name: &str) -> Option<usize> {
+        self.definitions.iter().position(|definition| {
+            definition.name.eq_ignore_ascii_case(name)
+                || definition
+                    .aliases
+                    .iter()
+                    .chain(&definition.extensions)
+                    .any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Language set by a Vim (`vim: ft=python`) or Emacs
+    /// (`-*- mode: python -*-`) modeline in `line`.
+    pub fn find_by_modeline(&self, line: &str) -> Option<usize> {
+        let name = vim_modeline(line).or_else(|| emacs_modeline(line))?;
+        self.find_by_name(name)
+    }
+
+    /// Language named by a `#!` line, looking through `env`.
+    pub fn find_by_shebang(&self, first_line: &str) -> Option<usize> {
+        let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+        let mut interpreter = words.next()?.rsplit('/').next()?;
+        if interpreter == "env" {
+            interpreter = words.find(|word| !word.starts_with('-'))?;
+        }
+
+        self.definitions
+            .iter()
+            .position(|definition| definition.matches_interpreter(interpreter))
+    }
+}
+
+// File type set by a modeline like `vim: set ft=python:` or `vi: syn=sh`.
+fn vim_modeline(line: &str) -> Option<&str> {
+    let options = ["vim:", "vi:", "ex:"].into_iter().find_map(|marker| {
+        let at = line.find(marker)?;
+        let starts_word = line[..at]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        starts_word.then(|| &line[at + marker.len()..])
+    })?;
+
+    options
+        .split([' ', '\t', ':'])
+        .find_map(|option| {
+            ["ft=", "filetype=", "syn=", "syntax="]
+                .into_iter()
+                .find_map(|name| option.strip_prefix(name))
+        })
+        .filter(|name| !name.is_empty())
+}
+
+// Major mode named by `-*- mode: python -*-` or just `-*- python -*-`.
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+
+    let mode = if variables.contains(':') {
+        variables.split(';').find_map(|variable| {
+            let (name, value) = variable.split_once(':')?;
+            name.trim().eq_ignore_ascii_case("mode").then_some(value)
+        })?
+    } else {
+        variables
+    };
+    Some(mode.trim()).filter(|mode| !mode.is_empty())
+}
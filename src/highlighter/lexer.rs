@@ -0,0 +1,170 @@
+use super::language::NumberRule;
+use super::{Annotation, HighlightType};
+
+/// Cursor over a single line that collects annotations as it goes.
+pub(super) struct Lexer<'a> {
+    pub line: &'a str,
+    // byte offset of the next unlexed character
+    pub pos: usize,
+    pub annotations: Vec<Annotation>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Lexer {
+            line,
+            pos: 0,
+            annotations: Vec::new(),
+        }
+    }
+
+    pub fn rest(&self) -> &'a str {
+        &self.line[self.pos..]
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pos >= self.line.len()
+    }
+
+    pub fn eat_while(&mut self, f: impl Fn(char) -> bool) {
+        let len = self
+            .rest()
+            .find(|c| !f(c))
+            .unwrap_or(self.line.len() - self.pos);
+        self.pos += len;
+    }
+
+    pub fn eat_rest(&mut self) {
+        self.pos = self.line.len();
+    }
+
+    // Annotates everything from `start` to the current position.
+    pub fn push(&mut self, start: usize, highlight_type: HighlightType) {
+        if self.pos > start {
+            self.annotations.push(Annotation {
+                start,
+                end: self.pos,
+                highlight_type,
+            });
+        }
+    }
+
+    /// Skips to the end of a block comment that is `depth` levels deep.
+    /// Returns the depth still open at the end of the line, 0 once closed.
+    pub fn block_comment(
+        &mut self,
+        open: &str,
+        close: &str,
+        nested: bool,
+        mut depth: usize,
+    ) -> usize {
+        loop {
+            let rest = self.rest();
+            let next_close = rest.find(close);
+            let next_open = rest.find(open).filter(|_| nested);
+
+            match (next_open, next_close) {
+                (Some(open_at), Some(close_at)) if open_at < close_at => {
+                    self.pos += open_at + open.len();
+                    depth += 1;
+                }
+                (_, Some(close_at)) => {
+                    self.pos += close_at + close.len();
+                    depth -= 1;
+                    if depth == 0 {
+                        return 0;
+                    }
+                }
+                (Some(open_at), None) => {
+                    self.pos += open_at + open.len();
+                    depth += 1;
+                }
+                (None, None) => {
+                    self.eat_rest();
+                    return depth;
+                }
+            }
+        }
+    }
+
+    /// Skips to just past the `end` delimiter of a string. With `escapes`
+    /// a backslash hides the character after it, and a trailing backslash
+    /// continues the string on the next line. Returns whether it closed.
+    pub fn string(&mut self, end: &str, escapes: bool) -> bool {
+        loop {
+            let rest = self.rest();
+            let Some(offset) = rest.find(end) else {
+                self.eat_rest();
+                return false;
+            };
+
+            let escape = rest[..offset].find('\\').filter(|_| escapes);
+            match escape {
+                Some(escape) => {
+                    self.pos += escape + 1;
+                    if let Some(c) = self.rest().chars().next() {
+                        self.pos += c.len_utf8();
+                    }
+                }
+                None => {
+                    self.pos += offset + end.len();
+                    return true;
+                }
+            }
+        }
+    }
+}
+
+/// End of the number literal at `start`.
+pub(super) fn number_end(line: &str, start: usize, rule: &NumberRule) -> usize {
+    let bytes = line.as_bytes();
+    let mut i = start;
+    let digits = |i: &mut usize, radix: u32| {
+        while *i < bytes.len()
+            && ((rule.underscores && bytes[*i] == b'_') || (bytes[*i] as char).is_digit(radix))
+        {
+            *i += 1;
+        }
+    };
+
+    let prefix = rule
+        .prefixes
+        .iter()
+        .find(|prefix| line[start..].starts_with(prefix.as_str()));
+
+    if let Some(prefix) = prefix {
+        let radix = match prefix.chars().last() {
+            Some('b' | 'B') => 2,
+            Some('o' | 'O') => 8,
+            _ => 16,
+        };
+        i += prefix.len();
+        digits(&mut i, radix);
+    } else {
+        digits(&mut i, 10);
+
+        // a fraction, but not a range `1..2` or a method call `1.max(2)`
+        if bytes.get(i) == Some(&b'.')
+            && !matches!(bytes.get(i + 1), Some(&b) if b == b'.' || b == b'_' || b.is_ascii_alphabetic())
+        {
+            i += 1;
+            digits(&mut i, 10);
+        }
+
+        if matches!(bytes.get(i), Some(b'e' | b'E')) {
+            let sign = usize::from(matches!(bytes.get(i + 1), Some(b'+' | b'-')));
+            if bytes.get(i + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                i += 1 + sign;
+                digits(&mut i, 10);
+            }
+        }
+    }
+
+    // type suffix like `u8` or `f64`
+    if rule.suffixes {
+        while i < bytes.len() && (bytes[i] == b'_' || bytes[i].is_ascii_alphanumeric()) {
+            i += 1;
+        }
+    }
+    i
+}
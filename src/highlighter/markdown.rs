@@ -0,0 +1,209 @@
+use super::lexer::Lexer;
+use super::{
+    Annotation, FileType, HighlightType, Highlighter, Languages, LexState, SyntaxHighlighter,
+};
+
+/// Tokenizer for Markdown. Code in fenced blocks is highlighted as the
+/// language named after the opening fence.
+pub(super) struct MarkdownSyntaxHighlighter;
+
+impl MarkdownSyntaxHighlighter {
+    // Lexes code spans and emphasis in running text.
+    fn inline(&self, lexer: &mut Lexer) {
+        while let Some(c) = lexer.rest().chars().next() {
+            let start = lexer.pos;
+            match c {
+                '\\' => {
+                    lexer.pos += 1;
+                    if let Some(c) = lexer.rest().chars().next() {
+                        lexer.pos += c.len_utf8();
+                    }
+                }
+                '`' => {
+                    let run = run_len(lexer.rest(), '`');
+                    let marker = &lexer.rest()[..run];
+                    match closing_run(&lexer.rest()[run..], marker) {
+                        Some(end) => {
+                            lexer.pos += run + end;
+                            lexer.push(start, HighlightType::String);
+                        }
+                        None => lexer.pos += run,
+                    }
+                }
+                '*' | '_' => {
+                    let run = run_len(lexer.rest(), c).min(3);
+                    let marker = &lexer.rest()[..run];
+                    let after = &lexer.rest()[run..];
+                    // `_` does not emphasize inside words, like `snake_case`
+                    let intraword = c == '_'
+                        && lexer.line[..start]
+                            .chars()
+                            .next_back()
+                            .is_some_and(char::is_alphanumeric);
+                    let end = (!intraword && after.starts_with(|c: char| !c.is_whitespace()))
+                        .then(|| emphasis_end(after, marker))
+                        .flatten();
+                    match end {
+                        Some(end) => {
+                            lexer.pos += run + end;
+                            lexer.push(start, HighlightType::Emphasis);
+                        }
+                        None => lexer.pos += run,
+                    }
+                }
+                _ => lexer.pos += c.len_utf8(),
+            }
+        }
+    }
+}
+
+impl SyntaxHighlighter for MarkdownSyntaxHighlighter {
+    fn highlight(&self, line: &str, state: LexState) -> (Vec<Annotation>, LexState) {
+        let mut lexer = Lexer::new(line);
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let rest = &line[indent..];
+
+        if let LexState::Fenced {
+            tildes,
+            len,
+            language,
+            inner,
+        } = state
+        {
+            let marker = if tildes { '~' } else { '`' };
+            let closing = indent < 4
+                && run_len(rest, marker) >= len
+                && rest.trim_start_matches(marker).trim().is_empty();
+            if closing {
+                lexer.eat_rest();
+                lexer.push(0, HighlightType::String);
+                return (lexer.annotations, LexState::Normal);
+            }
+
+            let (annotations, inner) = match language {
+                Some(file_type) => {
+                    Highlighter::create_syntax_highlighter(file_type).highlight(line, *inner)
+                }
+                None => {
+                    lexer.eat_rest();
+                    lexer.push(0, HighlightType::String);
+                    (lexer.annotations, *inner)
+                }
+            };
+            let state = LexState::Fenced {
+                tildes,
+                len,
+                language,
+                inner: Box::new(inner),
+            };
+            return (annotations, state);
+        }
+
+        // indented code blocks are left alone, since list items continue
+        // with indented lines too
+        if indent >= 4 {
+            return (lexer.annotations, LexState::Normal);
+        }
+
+        let fence = ['`', '~']
+            .into_iter()
+            .map(|marker| (marker, run_len(rest, marker)))
+            .find(|&(_, len)| len >= 3);
+        if let Some((marker, len)) = fence {
+            let info = rest[len..].trim();
+            if marker == '~' || !info.contains('`') {
+                let name = info
+                    .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+                    .next()
+                    .unwrap_or("");
+                let language = Languages::get().find_by_name(name).map(FileType);
+                lexer.eat_rest();
+                lexer.push(indent, HighlightType::String);
+                let state = LexState::Fenced {
+                    tildes: marker == '~',
+                    len,
+                    language,
+                    inner: Box::default(),
+                };
+                return (lexer.annotations, state);
+            }
+        }
+
+        let hashes = run_len(rest, '#');
+        if (1..=6).contains(&hashes)
+            && rest[hashes..]
+                .chars()
+                .next()
+                .is_none_or(char::is_whitespace)
+        {
+            lexer.eat_rest();
+            lexer.push(indent, HighlightType::Heading);
+            return (lexer.annotations, LexState::Normal);
+        }
+
+        // thematic breaks like `***` or `- - -`
+        let rule = rest.chars().find(|c| !c.is_whitespace());
+        if let Some(marker @ ('*' | '-' | '_')) = rule
+            && rest.chars().all(|c| c == marker || c.is_whitespace())
+            && rest.matches(marker).count() >= 3
+        {
+            lexer.eat_rest();
+            lexer.push(indent, HighlightType::Keyword);
+            return (lexer.annotations, LexState::Normal);
+        }
+
+        // block quote and list markers
+        lexer.pos = indent;
+        loop {
+            let start = lexer.pos;
+            let rest = lexer.rest();
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let marker = if rest.starts_with(['>', '-', '*', '+']) {
+                1
+            } else if (1..=9).contains(&digits) && rest[digits..].starts_with(['.', ')']) {
+                digits + 1
+            } else {
+                break;
+            };
+            let after = &rest[marker..];
+            if !(rest.starts_with('>')
+                || after.is_empty()
+                || after.starts_with(char::is_whitespace))
+            {
+                break;
+            }
+            lexer.pos += marker;
+            lexer.push(start, HighlightType::Keyword);
+            lexer.eat_while(char::is_whitespace);
+        }
+
+        self.inline(&mut lexer);
+        (lexer.annotations, LexState::Normal)
+    }
+}
+
+fn run_len(text: &str, c: char) -> usize {
+    text.len() - text.trim_start_matches(c).len()
+}
+
+// End of the code span closed by exactly `marker` in `text`, past the
+// closing backticks.
+fn closing_run(text: &str, marker: &str) -> Option<usize> {
+    let mut pos = 0;
+    while let Some(offset) = text[pos..].find(marker) {
+        let at = pos + offset;
+        let run = run_len(&text[at..], '`');
+        if run == marker.len() {
+            return Some(at + run);
+        }
+        pos = at + run;
+    }
+    None
+}
+
+// End of emphasis closed by `marker` in `text`, right after a non-space.
+fn emphasis_end(text: &str, marker: &str) -> Option<usize> {
+    text.match_indices(marker)
+        .find(|&(at, _)| at > 0 && !text[..at].ends_with(char::is_whitespace))
+        .map(|(at, _)| at + marker.len())
+}
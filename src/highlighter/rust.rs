@@ -0,0 +1,233 @@
+use super::lexer::{Lexer, number_end};
+use super::{Annotation, HighlightType, LanguageDefinition, LexState, SyntaxHighlighter};
+
+/// Tokenizer for Rust's lexical grammar. Keywords, types and literals come
+/// from the language definition that selects it with `lexer = "rust"`.
+pub(super) struct RustSyntaxHighlighter {
+    definition: &'static LanguageDefinition,
+}
+
+impl RustSyntaxHighlighter {
+    pub fn new(definition: &'static LanguageDefinition) -> Self {
+        Self { definition }
+    }
+
+    // Lexes the token at the lexer position, which is outside any
+    // multi-line construct. Returns the state the token leaves the line in.
+    fn next_token(&self, lexer: &mut Lexer) -> LexState {
+        let start = lexer.pos;
+        let rest = lexer.rest();
+        let Some(c) = rest.chars().next() else {
+            return LexState::Normal;
+        };
+
+        if rest.starts_with("//") {
+            let doc =
+                (rest.starts_with("///") && !rest.starts_with("////")) || rest.starts_with("//!");
+            lexer.eat_rest();
+            lexer.push(start, Self::comment_type(doc));
+            return LexState::Normal;
+        }
+        if rest.starts_with("/*") {
+            let doc =
+                (rest.starts_with("/**") && !rest.starts_with("/***") && !rest.starts_with("/**/"))
+                    || rest.starts_with("/*!");
+            lexer.pos += 2;
+            return finish(lexer, start, LexState::BlockComment { depth: 1, doc });
+        }
+
+        // string and char literals, with their b/c/r prefixes
+        let prefix = ["br", "cr", "b", "c", "r"]
+            .into_iter()
+            .find(|prefix| rest.starts_with(prefix))
+            .unwrap_or("");
+        let after_prefix = &rest[prefix.len()..];
+        if prefix.ends_with('r') {
+            let hashes = after_prefix.bytes().take_while(|&b| b == b'#').count();
+            if after_prefix[hashes..].starts_with('"') {
+                lexer.pos += prefix.len() + hashes + 1;
+                return finish(lexer, start, LexState::RawString(hashes));
+            }
+        } else if after_prefix.starts_with('"') {
+            lexer.pos += prefix.len() + 1;
+            return finish(lexer, start, LexState::String(0));
+        } else if after_prefix.starts_with('\'') && prefix != "c" {
+            lexer.pos += prefix.len();
+            self.quote(lexer, start);
+            return LexState::Normal;
+        }
+
+        if c == '#' && (rest[1..].starts_with('[') || rest[1..].starts_with("![")) {
+            lexer.pos += rest.find('[').unwrap_or(0) + 1;
+            return finish(lexer, start, LexState::Attribute(1));
+        }
+
+        if c == 'r' && rest[1..].starts_with('#') && rest[2..].starts_with(is_ident_start) {
+            // raw identifier, never a keyword
+            lexer.pos += 2;
+            lexer.eat_while(is_ident_continue);
+        } else if is_ident_start(c) {
+            self.identifier(lexer);
+        } else if c.is_ascii_digit() {
+            let before = &lexer.line[..start];
+            if before.ends_with('.') && !before.ends_with("..") {
+                // tuple field like `pair.0`
+                lexer.eat_while(|c| c.is_ascii_digit());
+            } else {
+                let rule = self.definition.numbers.as_ref();
+                lexer.pos = rule.map_or(start + 1, |rule| number_end(lexer.line, start, rule));
+                lexer.push(start, HighlightType::Number);
+            }
+        } else {
+            lexer.pos += c.len_utf8();
+        }
+
+        LexState::Normal
+    }
+
+    // A char literal or a lifetime at the `'` under the lexer, with any
+    // prefix starting at `start`.
+    fn quote(&self, lexer: &mut Lexer, start: usize) {
+        if let Some(end) = char_literal_end(lexer.line, lexer.pos) {
+            lexer.pos = end;
+            lexer.push(start, HighlightType::Character);
+            return;
+        }
+
+        lexer.pos += 1;
+        if lexer.rest().starts_with(is_ident_start) {
+            lexer.eat_while(is_ident_continue);
+            lexer.push(start, HighlightType::Lifetime);
+        }
+    }
+
+    fn identifier(&self, lexer: &mut Lexer) {
+        let start = lexer.pos;
+        lexer.eat_while(is_ident_continue);
+        let word = &lexer.line[start..lexer.pos];
+
+        let rest = lexer.rest();
+        let highlight_type = if rest.starts_with('!') && !rest.starts_with("!=") {
+            lexer.pos += 1;
+            HighlightType::Macro
+        } else if let Some(highlight_type) = self.definition.word_type(word) {
+            highlight_type
+        } else {
+            return;
+        };
+        lexer.push(start, highlight_type);
+    }
+
+    fn comment_type(doc: bool) -> HighlightType {
+        if doc {
+            HighlightType::DocComment
+        } else {
+            HighlightType::Comment
+        }
+    }
+}
+
+impl SyntaxHighlighter for RustSyntaxHighlighter {
+    fn highlight(&self, line: &str, state: LexState) -> (Vec<Annotation>, LexState) {
+        let mut lexer = Lexer::new(line);
+
+        let mut state = finish(&mut lexer, 0, state);
+        while state == LexState::Normal && !lexer.is_done() {
+            state = self.next_token(&mut lexer);
+        }
+
+        (lexer.annotations, state)
+    }
+}
+
+// Lexes the rest of the construct `state` describes, which began at `start`,
+// up to its end or the end of the line. Returns the state after it.
+fn finish(lexer: &mut Lexer, start: usize, state: LexState) -> LexState {
+    let (next, highlight_type) = match state {
+        LexState::Normal => return state,
+        LexState::BlockComment { depth, doc } => {
+            let depth = lexer.block_comment("/*", "*/", true, depth);
+            let next = match depth {
+                0 => LexState::Normal,
+                _ => LexState::BlockComment { depth, doc },
+            };
+            (next, RustSyntaxHighlighter::comment_type(doc))
+        }
+        LexState::String(_) => {
+            let next = if lexer.string("\"", true) {
+                LexState::Normal
+            } else {
+                state
+            };
+            (next, HighlightType::String)
+        }
+        LexState::RawString(hashes) => (raw_string(lexer, hashes), HighlightType::String),
+        LexState::Attribute(depth) => (attribute(lexer, depth), HighlightType::Attribute),
+        _ => return LexState::Normal,
+    };
+    lexer.push(start, highlight_type);
+    next
+}
+
+fn raw_string(lexer: &mut Lexer, hashes: usize) -> LexState {
+    while let Some(offset) = lexer.rest().find('"') {
+        lexer.pos += offset + 1;
+        if lexer.rest().bytes().take_while(|&b| b == b'#').count() >= hashes {
+            lexer.pos += hashes;
+            return LexState::Normal;
+        }
+    }
+
+    lexer.eat_rest();
+    LexState::RawString(hashes)
+}
+
+fn attribute(lexer: &mut Lexer, mut depth: usize) -> LexState {
+    while let Some(offset) = lexer.rest().find(['[', ']', '"']) {
+        lexer.pos += offset + 1;
+        match lexer.line.as_bytes()[lexer.pos - 1] {
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            _ => {
+                // a string argument, which may contain brackets
+                lexer.string("\"", true);
+                continue;
+            }
+        }
+        if depth == 0 {
+            return LexState::Normal;
+        }
+    }
+
+    lexer.eat_rest();
+    LexState::Attribute(depth)
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+// End of the char literal opened by the `'` at `start`, or `None` when the
+// quote starts a lifetime or label instead.
+fn char_literal_end(line: &str, start: usize) -> Option<usize> {
+    let rest = &line[start + 1..];
+    let mut chars = rest.char_indices();
+
+    let body_end = match chars.next()? {
+        (_, '\\') => match chars.next()? {
+            (_, 'u') => rest.find('}')? + 1,
+            (_, 'x') => 4,
+            (i, c) => i + c.len_utf8(),
+        },
+        (_, '\'') => return None,
+        (i, c) => i + c.len_utf8(),
+    };
+
+    rest.get(body_end..)?
+        .starts_with('\'')
+        .then_some(start + 1 + body_end + 1)
+}
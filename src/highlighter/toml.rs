@@ -0,0 +1,197 @@
+use super::lexer::{Lexer, number_end};
+use super::{Annotation, HighlightType, LanguageDefinition, LexState, SyntaxHighlighter};
+
+// String delimiters with whether they take escapes and span lines, indexed
+// by the kind in `LexState::String`. Longer ones come first.
+const STRINGS: &[(&str, bool, bool)] = &[
+    ("\"\"\"", true, true),
+    ("'''", false, true),
+    ("\"", true, false),
+    ("'", false, false),
+];
+
+/// Tokenizer for TOML, telling table headers and keys from values.
+pub(super) struct TomlSyntaxHighlighter {
+    definition: &'static LanguageDefinition,
+}
+
+impl TomlSyntaxHighlighter {
+    pub fn new(definition: &'static LanguageDefinition) -> Self {
+        Self { definition }
+    }
+
+    fn next_token(&self, lexer: &mut Lexer) -> LexState {
+        let start = lexer.pos;
+        let rest = lexer.rest();
+        let Some(c) = rest.chars().next() else {
+            return LexState::Normal;
+        };
+
+        if c == '#' {
+            lexer.eat_rest();
+            lexer.push(start, HighlightType::Comment);
+            return LexState::Normal;
+        }
+
+        let before = lexer.line[..start].trim_end();
+        if before.is_empty()
+            && let Some(end) = table_header_end(rest)
+        {
+            lexer.pos += end;
+            lexer.push(start, HighlightType::Heading);
+            return LexState::Normal;
+        }
+        // keys start a line or an entry of an inline table, and are
+        // followed by `=`
+        if (before.is_empty() || before.ends_with(['{', ',']))
+            && let Some(end) = dotted_key(rest)
+            && rest[end..].trim_start().starts_with('=')
+        {
+            lexer.pos += end;
+            lexer.push(start, HighlightType::Key);
+            return LexState::Normal;
+        }
+
+        if let Some(kind) = STRINGS
+            .iter()
+            .position(|(delimiter, ..)| rest.starts_with(delimiter))
+        {
+            lexer.pos += STRINGS[kind].0.len();
+            return finish(lexer, start, LexState::String(kind));
+        }
+
+        let unsigned = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+        if unsigned.starts_with("inf") || unsigned.starts_with("nan") {
+            lexer.pos += rest.len() - unsigned.len() + 3;
+            lexer.push(start, HighlightType::Number);
+        } else if unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+            let digits = start + rest.len() - unsigned.len();
+            lexer.pos = match datetime_end(lexer.line, digits) {
+                Some(end) => end,
+                None => self
+                    .definition
+                    .numbers
+                    .as_ref()
+                    .map_or(digits + 1, |rule| number_end(lexer.line, digits, rule)),
+            };
+            lexer.push(start, HighlightType::Number);
+        } else if is_bare_key_char(c) {
+            lexer.eat_while(is_bare_key_char);
+            if let Some(highlight_type) = self.definition.word_type(&lexer.line[start..lexer.pos]) {
+                lexer.push(start, highlight_type);
+            }
+        } else {
+            lexer.pos += c.len_utf8();
+        }
+
+        LexState::Normal
+    }
+}
+
+impl SyntaxHighlighter for TomlSyntaxHighlighter {
+    fn highlight(&self, line: &str, state: LexState) -> (Vec<Annotation>, LexState) {
+        let mut lexer = Lexer::new(line);
+
+        let mut state = finish(&mut lexer, 0, state);
+        while state == LexState::Normal && !lexer.is_done() {
+            lexer.eat_while(char::is_whitespace);
+            state = self.next_token(&mut lexer);
+        }
+
+        (lexer.annotations, state)
+    }
+}
+
+// Lexes the rest of a string of the given kind, which began at `start`.
+fn finish(lexer: &mut Lexer, start: usize, state: LexState) -> LexState {
+    let LexState::String(kind) = state else {
+        return LexState::Normal;
+    };
+    let Some(&(delimiter, escapes, multiline)) = STRINGS.get(kind) else {
+        return LexState::Normal;
+    };
+
+    let closed = lexer.string(delimiter, escapes);
+    lexer.push(start, HighlightType::String);
+    if closed || !multiline {
+        LexState::Normal
+    } else {
+        state
+    }
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c == '_' || c == '-' || c.is_ascii_alphanumeric()
+}
+
+// End of the key like `a."b".c` that `text` starts with.
+fn dotted_key(text: &str) -> Option<usize> {
+    let mut pos = 0;
+    loop {
+        let part = &text[pos..];
+        let len = match part.chars().next()? {
+            '"' => quoted_len(part, "\"", true)?,
+            '\'' => quoted_len(part, "'", false)?,
+            _ => part.find(|c| !is_bare_key_char(c)).unwrap_or(part.len()),
+        };
+        if len == 0 {
+            return None;
+        }
+        pos += len;
+
+        let after = text[pos..].trim_start();
+        let Some(next) = after.strip_prefix('.') else {
+            return Some(pos);
+        };
+        pos = text.len() - next.trim_start().len();
+    }
+}
+
+fn quoted_len(text: &str, quote: &str, escapes: bool) -> Option<usize> {
+    let mut lexer = Lexer::new(text);
+    lexer.pos = quote.len();
+    lexer.string(quote, escapes).then_some(lexer.pos)
+}
+
+// End of the `[table]` or `[[array.of.tables]]` header making up `text`.
+fn table_header_end(text: &str) -> Option<usize> {
+    // not the last line of a multi-line array, like `  "b"]`
+    if !text.starts_with('[') {
+        return None;
+    }
+    let brackets = if text.starts_with("[[") { 2 } else { 1 };
+    let inner = text.get(brackets..)?.trim_start();
+    let key_end = text.len() - inner.len() + dotted_key(inner)?;
+
+    let after = text[key_end..].trim_start();
+    let close = &"]]"[..brackets];
+    let trailing = after.strip_prefix(close)?.trim_start();
+    (trailing.is_empty() || trailing.starts_with('#'))
+        .then_some(text.len() - after.len() + brackets)
+}
+
+// End of the date, time or date-time at `start`, if there is one.
+fn datetime_end(line: &str, start: usize) -> Option<usize> {
+    let bytes = &line.as_bytes()[start..];
+    let digits = |at: usize, count: usize| {
+        bytes
+            .get(at..at + count)
+            .is_some_and(|digits| digits.iter().all(u8::is_ascii_digit))
+    };
+    let date = digits(0, 4) && bytes.get(4) == Some(&b'-');
+    let time = digits(0, 2) && bytes.get(2) == Some(&b':');
+    if !date && !time {
+        return None;
+    }
+
+    let mut i = 0;
+    while let Some(&b) = bytes.get(i) {
+        match b {
+            b'0'..=b'9' | b'-' | b':' | b'.' | b'+' | b'T' | b't' | b'Z' | b'z' => i += 1,
+            // a space may separate the date from the time
+            b' ' if date && digits(i + 1, 2) && bytes.get(i + 3) == Some(&b':') => i += 1,
+            _ => break,
+        }
+    }
+    Some(start + i)
+}
@@ -0,0 +1,215 @@
+use super::lexer::{Lexer, number_end};
+use super::{Annotation, HighlightType, LanguageDefinition, LexState, SyntaxHighlighter};
+
+// Characters that structure flow collections like `{a: 1, b: [2]}`.
+const FLOW_CHARS: &[char] = &[',', '[', ']', '{', '}'];
+
+/// Tokenizer for YAML: keys, anchors and tags, quoted strings and block
+/// scalars, and plain scalars that are numbers or literals.
+pub(super) struct YamlSyntaxHighlighter {
+    definition: &'static LanguageDefinition,
+}
+
+impl YamlSyntaxHighlighter {
+    pub fn new(definition: &'static LanguageDefinition) -> Self {
+        Self { definition }
+    }
+
+    // Plain scalars are only highlighted when they are a number or a
+    // literal as a whole.
+    fn scalar_type(&self, scalar: &str) -> Option<HighlightType> {
+        if let Some(highlight_type) = self.definition.word_type(scalar) {
+            return Some(highlight_type);
+        }
+        let rule = self.definition.numbers.as_ref()?;
+        let digits = scalar.strip_prefix(['+', '-']).unwrap_or(scalar);
+        (digits.starts_with(|c: char| c.is_ascii_digit())
+            && number_end(digits, 0, rule) == digits.len())
+        .then_some(HighlightType::Number)
+    }
+}
+
+impl SyntaxHighlighter for YamlSyntaxHighlighter {
+    fn highlight(&self, line: &str, state: LexState) -> (Vec<Annotation>, LexState) {
+        let mut lexer = Lexer::new(line);
+        let indent = line.len() - line.trim_start_matches(' ').len();
+
+        match state {
+            LexState::BlockScalar(parent) if indent > parent || line.trim().is_empty() => {
+                lexer.pos = indent;
+                lexer.eat_rest();
+                lexer.push(indent, HighlightType::String);
+                return (lexer.annotations, state);
+            }
+            LexState::String(kind) => {
+                if !quoted(&mut lexer, 0, kind) {
+                    return (lexer.annotations, state);
+                }
+            }
+            _ => {
+                // document markers
+                if (line.starts_with("---") || line.starts_with("..."))
+                    && line[3..].trim().is_empty()
+                {
+                    lexer.pos = 3;
+                    lexer.push(0, HighlightType::Keyword);
+                }
+            }
+        }
+
+        let mut state = LexState::Normal;
+        let mut key_allowed = lexer.pos == 0;
+        let mut flow_depth = 0usize;
+        loop {
+            lexer.eat_while(char::is_whitespace);
+            let start = lexer.pos;
+            let rest = lexer.rest();
+            let Some(c) = rest.chars().next() else {
+                break;
+            };
+            let flow = flow_depth > 0;
+
+            if c == '#' && (start == 0 || line[..start].ends_with(char::is_whitespace)) {
+                lexer.eat_rest();
+                lexer.push(start, HighlightType::Comment);
+                break;
+            }
+            // a sequence entry may hold a mapping
+            if (c == '-' || c == '?') && (rest.len() == 1 || rest[1..].starts_with(' ')) {
+                lexer.pos += 1;
+                continue;
+            }
+            if key_allowed && let Some((key_end, colon_end)) = key_end(rest, flow) {
+                lexer.pos += key_end;
+                lexer.push(start, HighlightType::Key);
+                lexer.pos = start + colon_end;
+                key_allowed = false;
+                continue;
+            }
+
+            match c {
+                '[' | '{' => {
+                    flow_depth += 1;
+                    lexer.pos += 1;
+                    key_allowed = true;
+                    continue;
+                }
+                ',' => {
+                    lexer.pos += 1;
+                    key_allowed = true;
+                    continue;
+                }
+                ']' | '}' => {
+                    flow_depth = flow_depth.saturating_sub(1);
+                    lexer.pos += 1;
+                }
+                // anchors, aliases and tags
+                '&' | '*' | '!' => {
+                    lexer.eat_while(|c| !(c.is_whitespace() || (flow && FLOW_CHARS.contains(&c))));
+                    lexer.push(start, HighlightType::Attribute);
+                    continue;
+                }
+                '"' | '\'' => {
+                    lexer.pos += 1;
+                    let kind = usize::from(c == '\'');
+                    if !quoted(&mut lexer, start, kind) {
+                        return (lexer.annotations, LexState::String(kind));
+                    }
+                }
+                '|' | '>'
+                    if rest[1..]
+                        .trim_start_matches(is_block_indicator)
+                        .trim_start()
+                        .chars()
+                        .next()
+                        .is_none_or(|c| c == '#') =>
+                {
+                    lexer.pos += 1;
+                    lexer.eat_while(is_block_indicator);
+                    lexer.push(start, HighlightType::Attribute);
+                    state = LexState::BlockScalar(indent);
+                }
+                _ => {
+                    let end = plain_scalar_end(rest, flow);
+                    lexer.pos += end;
+                    if let Some(highlight_type) = self.scalar_type(&rest[..end]) {
+                        lexer.push(start, highlight_type);
+                    }
+                }
+            }
+            key_allowed = false;
+        }
+
+        (lexer.annotations, state)
+    }
+}
+
+// Chomping and indentation indicators after `|` or `>`.
+fn is_block_indicator(c: char) -> bool {
+    c == '+' || c == '-' || c.is_ascii_digit()
+}
+
+// Lexes the rest of a double (kind 0) or single (kind 1) quoted scalar,
+// which began at `start`. Returns whether it closed on this line.
+fn quoted(lexer: &mut Lexer, start: usize, kind: usize) -> bool {
+    // single quotes are escaped by doubling them, which lexes the same as
+    // two strings in a row
+    let closed = if kind == 0 {
+        lexer.string("\"", true)
+    } else {
+        lexer.string("'", false)
+    };
+    lexer.push(start, HighlightType::String);
+    closed
+}
+
+// End of the plain scalar that `text` starts with, before any comment.
+fn plain_scalar_end(text: &str, flow: bool) -> usize {
+    let mut end = text.len();
+    if let Some(comment) = text.find(" #") {
+        end = comment;
+    }
+    if flow && let Some(flow_char) = text.find(FLOW_CHARS) {
+        end = end.min(flow_char);
+    }
+    text[..end].trim_end().len()
+}
+
+// Ends of the mapping key that `text` starts with and of the `:` after it.
+fn key_end(text: &str, flow: bool) -> Option<(usize, usize)> {
+    let end = match text.chars().next()? {
+        '"' | '\'' => {
+            let quote = &text[..1];
+            let mut lexer = Lexer::new(text);
+            lexer.pos = 1;
+            if !lexer.string(quote, quote == "\"") {
+                return None;
+            }
+            lexer.pos
+        }
+        '#' | '&' | '*' | '!' | '|' | '>' | '%' | '@' | '`' => return None,
+        c if FLOW_CHARS.contains(&c) => return None,
+        _ => {
+            let end = text.match_indices(':').map(|(i, _)| i).find(|&i| {
+                text[i + 1..]
+                    .chars()
+                    .next()
+                    .is_none_or(|c| c.is_whitespace() || (flow && FLOW_CHARS.contains(&c)))
+            })?;
+            let key = &text[..end];
+            if key.contains(" #") || (flow && key.contains(FLOW_CHARS)) {
+                return None;
+            }
+            key.trim_end().len()
+        }
+    };
+
+    let after = &text[end..];
+    let colon = text.len() - after.trim_start().len();
+    text[colon..]
+        .strip_prefix(':')?
+        .chars()
+        .next()
+        .is_none_or(|c| c.is_whitespace() || (flow && FLOW_CHARS.contains(&c)))
+        .then_some((end, colon + 1))
+}
@@ -0,0 +1,194 @@
+use crate::buffer::Position;
+
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Insert { at: Position, text: String },
+    Delete { at: Position, text: String },
+}
+
+impl Edit {
+    /// Position just past the text carried by this edit.
+    pub fn end(&self) -> Position {
+        let (at, text) = match self {
+            Edit::Insert { at, text } | Edit::Delete { at, text } => (at, text),
+        };
+        Position::after(*at, text)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub edits: Vec<Edit>,
+    pub cursor_before: Position,
+    pub cursor_after: Position,
+}
+
+impl Transaction {
+    // Folds `edit` into this transaction when it continues the last edit:
+    // typing right after the previous insert, or backspace/delete next to
+    // the previous deletion.
+    fn try_merge(&mut self, edit: &Edit, cursor_after: Position) -> bool {
+        let Some(last) = self.edits.last_mut() else {
+            return false;
+        };
+
+        let merged = match (last, edit) {
+            (
+                Edit::Insert { at, text },
+                Edit::Insert {
+                    at: new_at,
+                    text: new_text,
+                },
+            ) => {
+                if text.contains('\n')
+                    || new_text.contains('\n')
+                    || *new_at != Position::after(*at, text)
+                {
+                    false
+                } else {
+                    text.push_str(new_text);
+                    true
+                }
+            }
+            (
+                Edit::Delete { at, text },
+                Edit::Delete {
+                    at: new_at,
+                    text: new_text,
+                },
+            ) => {
+                if new_text.contains('\n') || text.contains('\n') {
+                    false
+                } else if new_at == at {
+                    // forward delete keeps removing at the same spot
+                    text.push_str(new_text);
+                    true
+                } else if edit.end() == *at {
+                    // backspace eats the text in front of the previous deletion
+                    text.insert_str(0, new_text);
+                    *at = *new_at;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        };
+
+        if merged {
+            self.cursor_after = cursor_after;
+        }
+        merged
+    }
+}
+
+/// Undo/redo log of every edit applied to a buffer.
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    sealed: bool,
+    // nesting depth of begin_group/end_group pairs
+    group_depth: usize,
+    group_open: bool,
+    // undo stack depth matching the file on disk, `None` once unreachable
+    save_point: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            sealed: true,
+            group_depth: 0,
+            group_open: false,
+            save_point: Some(0),
+        }
+    }
+}
+
+impl History {
+    pub fn record(&mut self, edit: Edit, cursor_before: Position, cursor_after: Position) {
+        self.redo_stack.clear();
+        if self.save_point > Some(self.undo_stack.len()) {
+            self.save_point = None;
+        }
+
+        if self.group_open
+            && let Some(last) = self.undo_stack.last_mut()
+        {
+            last.edits.push(edit);
+            last.cursor_after = cursor_after;
+            return;
+        }
+
+        if !self.sealed
+            && let Some(last) = self.undo_stack.last_mut()
+            && last.try_merge(&edit, cursor_after)
+        {
+            return;
+        }
+
+        self.undo_stack.push(Transaction {
+            edits: vec![edit],
+            cursor_before,
+            cursor_after,
+        });
+        self.sealed = false;
+        self.group_open = self.group_depth > 0;
+    }
+
+    /// Collects every edit recorded until the matching `end_group` into one
+    /// undo step. Groups may nest; only the outermost one counts.
+    pub fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.seal();
+        }
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth == 0 {
+            self.group_open = false;
+            self.seal();
+        }
+    }
+
+    /// Ends the current undo step, so the next edit starts a new one.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Transaction> {
+        self.sealed = true;
+        let transaction = self.undo_stack.pop()?;
+        self.redo_stack.push(transaction.clone());
+        Some(transaction)
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Transaction> {
+        self.sealed = true;
+        let transaction = self.redo_stack.pop()?;
+        self.undo_stack.push(transaction.clone());
+        Some(transaction)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.sealed = true;
+        self.save_point = Some(0);
+    }
+
+    /// Remembers the current state as the one written to disk.
+    pub fn mark_saved(&mut self) {
+        self.seal();
+        self.save_point = Some(self.undo_stack.len());
+    }
+
+    /// Whether undo/redo has brought the text back to what was last saved.
+    pub fn is_at_save_point(&self) -> bool {
+        self.save_point == Some(self.undo_stack.len())
+    }
+}
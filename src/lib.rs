@@ -1,5 +1,11 @@
 pub mod buffer;
+pub mod config;
 pub mod editor;
 pub mod highlighter;
+pub mod history;
+pub mod prompt;
+pub mod replace;
+pub mod search;
 pub mod terminal;
+pub mod theme;
 pub mod view;
@@ -1,8 +1,11 @@
+use std::process;
+
 use editra::{self, editor::Editor};
 
 fn main() {
     let mut editor = Editor::default();
     if let Err(e) = editor.run() {
-        println!("Error: {}", e);
+        eprintln!("Error: {}", e);
+        process::exit(1);
     }
 }
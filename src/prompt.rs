@@ -0,0 +1,222 @@
+use std::io::{self};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::terminal::Terminal;
+
+/// What a prompt is asking for. Each kind keeps its own input history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromptKind {
+    Search,
+    ReplacePattern,
+    ReplaceWith,
+    SaveAs,
+    /// Yes/no question about creating a missing directory while saving.
+    CreateDirectory,
+    /// Language to highlight the buffer as.
+    FileType,
+    Theme,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PromptAction {
+    /// The input text changed.
+    Changed,
+    Submit(String),
+    Cancel,
+    /// The key only moved the cursor or was not meant for the prompt.
+    None,
+}
+
+/// A single-line input shown on the bottom row of the screen.
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub label: String,
+    /// Extra text shown after the input, e.g. "(no match)".
+    pub hint: Option<String>,
+    input: String,
+    // byte offset into `input`, always on a grapheme boundary
+    cursor: usize,
+    history: Vec<String>,
+    // entry shown while browsing history, `None` for the draft
+    history_idx: Option<usize>,
+    draft: String,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind, label: String, history: Vec<String>) -> Self {
+        Prompt {
+            kind,
+            label,
+            hint: None,
+            input: String::new(),
+            cursor: 0,
+            history,
+            history_idx: None,
+            draft: String::new(),
+        }
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn set_input(&mut self, input: &str) {
+        self.input = input.to_owned();
+        self.cursor = self.input.len();
+    }
+
+    /// Inserts `text` at the cursor, flattening line breaks to spaces.
+    pub fn insert_str(&mut self, text: &str) {
+        let text = text.replace("\r\n", " ").replace(['\r', '\n'], " ");
+        self.input.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    pub fn handle_key(&mut self, key_event: &KeyEvent) -> PromptAction {
+        let control = key_event.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key_event.code {
+            KeyCode::Esc => return PromptAction::Cancel,
+            KeyCode::Enter => return PromptAction::Submit(self.input.clone()),
+
+            KeyCode::Left => self.cursor = self.previous_boundary(),
+            KeyCode::Right => self.cursor = self.next_boundary(),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::Char('a') if control => self.cursor = 0,
+            KeyCode::End => self.cursor = self.input.len(),
+            KeyCode::Char('e') if control => self.cursor = self.input.len(),
+            KeyCode::Up => return self.browse_history(true),
+            KeyCode::Down => return self.browse_history(false),
+
+            KeyCode::Backspace if self.cursor > 0 => {
+                let start = self.previous_boundary();
+                self.input.replace_range(start..self.cursor, "");
+                self.cursor = start;
+                return PromptAction::Changed;
+            }
+            KeyCode::Delete if self.cursor < self.input.len() => {
+                let end = self.next_boundary();
+                self.input.replace_range(self.cursor..end, "");
+                return PromptAction::Changed;
+            }
+            KeyCode::Char('u') if control => {
+                self.input.replace_range(..self.cursor, "");
+                self.cursor = 0;
+                return PromptAction::Changed;
+            }
+            KeyCode::Char('k') if control => {
+                self.input.truncate(self.cursor);
+                return PromptAction::Changed;
+            }
+            KeyCode::Char(c)
+                if !key_event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.input.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+                return PromptAction::Changed;
+            }
+            _ => {}
+        }
+
+        PromptAction::None
+    }
+
+    pub fn render(&self) -> Result<(), io::Error> {
+        let (width, height) = Terminal::size();
+        let width = width as usize;
+
+        Terminal::move_cursor_to(0, height - 1)?;
+        Terminal::clear_line()?;
+
+        let (visible, _) = self.visible_input(width);
+        Terminal::print(&self.label)?;
+        Terminal::print(visible)?;
+
+        if let Some(hint) = &self.hint {
+            let used = self.label.width() + visible.width();
+            if used + hint.width() < width {
+                Terminal::print(format!(" {}", hint))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Screen column of the prompt cursor.
+    pub fn cursor_column(&self) -> usize {
+        let width = Terminal::size().0 as usize;
+        let (_, skipped) = self.visible_input(width);
+        self.label.width() + self.input[skipped..self.cursor].width()
+    }
+
+    // Part of the input that fits next to the label, scrolled so the cursor
+    // stays visible. Also returns how many bytes were skipped at the front.
+    fn visible_input(&self, width: usize) -> (&str, usize) {
+        let room = width.saturating_sub(self.label.width() + 1);
+        let mut skipped = 0;
+
+        while self.input[skipped..self.cursor].width() > room {
+            skipped += self.input[skipped..]
+                .graphemes(true)
+                .next()
+                .map_or(1, str::len);
+        }
+
+        let mut end = skipped;
+        for g in self.input[skipped..].graphemes(true) {
+            if self.input[skipped..end + g.len()].width() > room {
+                break;
+            }
+            end += g.len();
+        }
+
+        (&self.input[skipped..end], skipped)
+    }
+
+    fn browse_history(&mut self, older: bool) -> PromptAction {
+        if self.history.is_empty() {
+            return PromptAction::None;
+        }
+
+        let next = match (self.history_idx, older) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => return PromptAction::None,
+            (Some(0), true) => Some(0),
+            (Some(idx), true) => Some(idx - 1),
+            (Some(idx), false) if idx + 1 < self.history.len() => Some(idx + 1),
+            (Some(_), false) => None,
+        };
+
+        if self.history_idx.is_none() {
+            self.draft = self.input.clone();
+        }
+        self.history_idx = next;
+
+        let input = match next {
+            Some(idx) => self.history[idx].clone(),
+            None => self.draft.clone(),
+        };
+        self.set_input(&input);
+
+        PromptAction::Changed
+    }
+
+    fn previous_boundary(&self) -> usize {
+        self.input[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.input[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+}
@@ -0,0 +1,155 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::buffer::{Buffer, Position};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaceStage {
+    Pattern,
+    Replacement,
+    Confirm,
+}
+
+#[derive(Default)]
+pub struct Replace {
+    pub stage: Option<ReplaceStage>,
+    pub pattern: String,
+    pub replacement: String,
+    pub regex_mode: bool,
+    pub case_sensitive: bool,
+    pub count: usize,
+    pub current: Option<(Position, Position)>,
+    regex: Option<Regex>,
+}
+
+impl Replace {
+    pub fn prompt_label(&self) -> String {
+        match self.stage {
+            Some(ReplaceStage::Replacement) => format!("Replace {} with: ", self.pattern),
+            _ => {
+                let case = if self.case_sensitive { "Aa" } else { "aa" };
+                let mode = if self.regex_mode { ".*" } else { "ab" };
+                format!("Replace [{} {}]: ", case, mode)
+            }
+        }
+    }
+
+    /// Builds the matcher for the current pattern. Literal patterns are
+    /// escaped, so both modes share the same search code.
+    pub fn compile(&mut self) -> Result<(), regex::Error> {
+        let pattern = if self.regex_mode {
+            self.pattern.clone()
+        } else {
+            regex::escape(&self.pattern)
+        };
+
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()?;
+        self.regex = Some(regex);
+        Ok(())
+    }
+
+    /// First match at or after `from`, up to the end of the buffer. An empty
+    /// match right at `from` is skipped unless `inclusive`, so zero-width
+    /// patterns still make progress.
+    pub fn find_from(
+        &self,
+        buffer: &Buffer,
+        from: Position,
+        inclusive: bool,
+    ) -> Option<(Position, Position)> {
+        let regex = self.regex.as_ref()?;
+
+        for (line_idx, line) in buffer.lines_at(from.line).enumerate() {
+            let line_idx = from.line + line_idx;
+            let start = if line_idx == from.line { from.byte } else { 0 };
+
+            let mut found = regex.find_at(&line, start);
+            if let Some(m) = found
+                && !inclusive
+                && line_idx == from.line
+                && m.is_empty()
+                && m.start() == start
+            {
+                found = line[start..]
+                    .chars()
+                    .next()
+                    .and_then(|c| regex.find_at(&line, start + c.len_utf8()));
+            }
+
+            if let Some(m) = found {
+                return Some((
+                    Position {
+                        line: line_idx,
+                        byte: m.start(),
+                    },
+                    Position {
+                        line: line_idx,
+                        byte: m.end(),
+                    },
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Replacement text for the match starting at byte `start` of `line`,
+    /// with `$1`-style references expanded in regex mode.
+    pub fn expand(&self, line: &str, start: usize) -> String {
+        let mut expanded = String::new();
+        let Some(captures) = self.regex.as_ref().and_then(|r| r.captures_at(line, start)) else {
+            return expanded;
+        };
+
+        if self.regex_mode {
+            captures.expand(&self.replacement, &mut expanded);
+        } else {
+            expanded.push_str(&self.replacement);
+        }
+        expanded
+    }
+
+    /// Rewrites every match in `line` starting at or after `from_byte`.
+    /// Returns the new line and the number of replacements made.
+    pub fn replace_in_line(&self, line: &str, from_byte: usize) -> (String, usize) {
+        let Some(regex) = self.regex.as_ref() else {
+            return (line.to_owned(), 0);
+        };
+
+        let mut replaced = String::with_capacity(line.len());
+        let mut last_end = 0;
+        let mut count = 0;
+        // matching starts at `from_byte`, so that a match before it cannot
+        // hide one starting there
+        let mut at = from_byte;
+
+        while let Some(captures) = regex.captures_at(line, at) {
+            let m = captures.get(0).unwrap();
+            // an empty match right after the previous match is no new one
+            let skip = m.is_empty() && count > 0 && m.start() == last_end;
+            if !skip {
+                replaced.push_str(&line[last_end..m.start()]);
+                if self.regex_mode {
+                    captures.expand(&self.replacement, &mut replaced);
+                } else {
+                    replaced.push_str(&self.replacement);
+                }
+                last_end = m.end();
+                count += 1;
+            }
+
+            at = if m.is_empty() {
+                match line[m.end()..].chars().next() {
+                    Some(c) => m.end() + c.len_utf8(),
+                    None => break,
+                }
+            } else {
+                m.end()
+            };
+        }
+        replaced.push_str(&line[last_end..]);
+
+        (replaced, count)
+    }
+}
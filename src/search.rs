@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::buffer::{Buffer, Position};
+
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    pub case_sensitive: bool,
+    pub active: bool,
+    // cursor when the prompt was opened, restored on cancel
+    pub origin: Option<Position>,
+    regex: Option<Regex>,
+}
+
+impl Search {
+    pub fn prompt_label(&self) -> String {
+        let case = if self.case_sensitive { "Aa" } else { "aa" };
+        format!("Search [{}]: ", case)
+    }
+
+    /// Builds the matcher for the current query, which is matched
+    /// literally.
+    pub fn compile(&mut self) {
+        self.regex = if self.query.is_empty() {
+            None
+        } else {
+            RegexBuilder::new(&regex::escape(&self.query))
+                .case_insensitive(!self.case_sensitive)
+                .build()
+                .ok()
+        };
+    }
+
+    /// Byte ranges of every non-overlapping match of the query in `line`.
+    pub fn matches_in_line(&self, line: &str) -> Vec<Range<usize>> {
+        self.regex.as_ref().map_or_else(Vec::new, |regex| {
+            regex.find_iter(line).map(|m| m.range()).collect()
+        })
+    }
+
+    /// First match starting at or after `from` (just after it unless
+    /// `inclusive`), wrapping around the end of the buffer.
+    pub fn find_next(
+        &self,
+        buffer: &Buffer,
+        from: Position,
+        inclusive: bool,
+    ) -> Option<(Position, Position)> {
+        let regex = self.regex.as_ref()?;
+        let line_count = buffer.line_count();
+        // the line of `from` comes around again at the end
+        let lines = buffer.lines_at(from.line).chain(buffer.lines_at(0));
+
+        for (step, line) in lines.take(line_count + 1).enumerate() {
+            let line_idx = (from.line + step) % line_count;
+            // most lines have no match at all
+            if !regex.is_match(&line) {
+                continue;
+            }
+
+            let found = self.matches_in_line(&line).into_iter().find(|m| {
+                if step == 0 {
+                    m.start > from.byte || (inclusive && m.start == from.byte)
+                } else if step == line_count {
+                    m.start <= from.byte
+                } else {
+                    true
+                }
+            });
+
+            if let Some(m) = found {
+                return Some(Self::to_positions(line_idx, m));
+            }
+        }
+
+        None
+    }
+
+    /// Last match starting before `from`, wrapping around the start of the
+    /// buffer.
+    pub fn find_previous(&self, buffer: &Buffer, from: Position) -> Option<(Position, Position)> {
+        let line_count = buffer.line_count();
+
+        for step in 0..=line_count {
+            let line_idx = (from.line + line_count * 2 - step) % line_count;
+            let line = buffer.line(line_idx)?;
+
+            let found = self.matches_in_line(&line).into_iter().rfind(|m| {
+                if step == 0 {
+                    m.start < from.byte
+                } else if step == line_count {
+                    m.start >= from.byte
+                } else {
+                    true
+                }
+            });
+
+            if let Some(m) = found {
+                return Some(Self::to_positions(line_idx, m));
+            }
+        }
+
+        None
+    }
+
+    fn to_positions(line: usize, range: Range<usize>) -> (Position, Position) {
+        (
+            Position {
+                line,
+                byte: range.start,
+            },
+            Position {
+                line,
+                byte: range.end,
+            },
+        )
+    }
+}
@@ -1,70 +1,145 @@
 use std::{
+    cell::RefCell,
     fmt::Display,
-    io::{self, Write, stdout},
+    io::{self},
+    sync::OnceLock,
+    time::Duration,
 };
 
-use crossterm::{
-    Command, cursor, queue,
-    style::Print,
-    terminal::{self, Clear, disable_raw_mode, enable_raw_mode},
-};
+use crossterm::event::Event;
+
+use crate::theme::Style;
+
+mod backend;
+mod colors;
+mod memory;
+mod screen;
+
+pub use backend::{Backend, CrosstermBackend};
+pub use colors::ColorSupport;
+pub use memory::MemoryBackend;
+use screen::Screen;
+
+static COLOR_SUPPORT: OnceLock<ColorSupport> = OnceLock::new();
+
+// Columns and rows assumed when the backend cannot tell its size.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+thread_local! {
+    static BACKEND: RefCell<Box<dyn Backend>> = RefCell::new(Box::new(CrosstermBackend::default()));
+    static SCREEN: RefCell<Screen> = RefCell::new(Screen::default());
+}
 
 pub struct Terminal {}
 
 impl Terminal {
+    /// Makes this thread's terminal `backend`, instead of the one the
+    /// editor runs in.
+    pub fn set_backend(backend: impl Backend + 'static) {
+        BACKEND.set(Box::new(backend));
+        SCREEN.with_borrow_mut(Screen::invalidate);
+    }
+
+    fn with_backend<T>(f: impl FnOnce(&mut dyn Backend) -> T) -> T {
+        BACKEND.with_borrow_mut(|backend| f(backend.as_mut()))
+    }
+
+    /// Columns and rows of the terminal, or the usual 80 by 24 when the
+    /// backend cannot tell.
     pub fn size() -> (u16, u16) {
-        terminal::size().unwrap()
+        Self::with_backend(|backend| backend.size()).unwrap_or(DEFAULT_SIZE)
+    }
+
+    // Drawing goes through the screen grid; `execute` sends the backend
+    // what changed.
+    fn draw(f: impl FnOnce(&mut Screen)) -> Result<(), io::Error> {
+        SCREEN.with_borrow_mut(f);
+        Ok(())
     }
 
     pub fn clear_line() -> Result<(), io::Error> {
-        Self::queue_command(Clear(terminal::ClearType::CurrentLine))
+        Self::draw(Screen::clear_line)
     }
 
     pub fn clear_terminal() -> Result<(), io::Error> {
-        Self::queue_command(Clear(terminal::ClearType::All))?;
+        let (width, height) = Self::size();
+        Self::draw(|screen| {
+            screen.resize(width as usize, height as usize);
+            screen.clear();
+            screen.move_to(0, 0);
+        })
+    }
 
-        Self::queue_command(Clear(terminal::ClearType::Purge))?;
-        Self::move_cursor_to(0, 0)?;
+    pub fn print<T: Display>(val: T) -> Result<(), io::Error> {
+        Self::draw(|screen| screen.print(&val.to_string()))
+    }
 
-        Ok(())
+    /// Hands `text` to the host terminal's clipboard.
+    pub fn copy_to_host_clipboard(text: &str) -> Result<(), io::Error> {
+        Self::with_backend(|backend| backend.copy_to_clipboard(text))
     }
 
-    pub fn print<T: Display>(val: T) -> Result<(), io::Error> {
-        Self::queue_command(Print(val))
+    /// Colors the terminal can show, detected on first use.
+    pub fn color_support() -> ColorSupport {
+        *COLOR_SUPPORT.get_or_init(ColorSupport::detect)
+    }
+
+    /// Draws what is printed from now on in `style`.
+    pub fn set_style(style: &Style) -> Result<(), io::Error> {
+        Self::draw(|screen| screen.set_style(*style))
     }
 
     pub fn terminate() -> Result<(), io::Error> {
-        disable_raw_mode()?;
-        Ok(())
+        Self::execute()?;
+        Self::with_backend(|backend| backend.terminate())
     }
 
     pub fn initialize() -> Result<(), io::Error> {
-        enable_raw_mode()?;
+        Self::with_backend(|backend| backend.initialize())?;
         Self::clear_terminal()?;
         Self::move_cursor_to(0, 0)?;
         Self::execute()?;
         Ok(())
     }
 
+    /// Gives the terminal back to the shell until the editor is resumed,
+    /// then redraws everything.
+    pub fn suspend() -> Result<(), io::Error> {
+        Self::with_backend(|backend| backend.suspend())?;
+        SCREEN.with_borrow_mut(Screen::invalidate);
+        Ok(())
+    }
+
     pub fn hide_cursor() -> Result<(), io::Error> {
-        Self::queue_command(cursor::Hide)
+        Self::draw(|screen| screen.set_cursor_visible(false))
     }
 
     pub fn show_cursor() -> Result<(), io::Error> {
-        Self::queue_command(cursor::Show)
+        Self::draw(|screen| screen.set_cursor_visible(true))
     }
 
     pub fn move_cursor_to(x: u16, y: u16) -> Result<(), io::Error> {
-        Self::queue_command(cursor::MoveTo(x, y))
+        Self::draw(|screen| screen.move_to(x as usize, y as usize))
     }
 
-    pub fn queue_command<T: Command>(command: T) -> Result<(), io::Error> {
-        queue!(stdout(), command)?;
-        Ok(())
+    /// Whether an input event arrives within `timeout`.
+    pub fn poll_event(timeout: Duration) -> Result<bool, io::Error> {
+        Self::with_backend(|backend| backend.poll_event(timeout))
+    }
+
+    pub fn read_event() -> Result<Event, io::Error> {
+        let event = Self::with_backend(|backend| backend.read_event())?;
+        // the terminal may have moved things around while resizing
+        if matches!(event, Event::Resize(..)) {
+            SCREEN.with_borrow_mut(Screen::invalidate);
+        }
+        Ok(event)
     }
 
     pub fn execute() -> Result<(), io::Error> {
-        stdout().flush()?;
-        Ok(())
+        Self::with_backend(|backend| {
+            SCREEN.with_borrow_mut(|screen| screen.flush(backend))?;
+            backend.flush()
+        })
     }
 }
@@ -0,0 +1,307 @@
+use std::{
+    io::{self, Stdout, Write, stdout},
+    panic,
+    sync::{
+        Once,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use crossterm::{
+    cursor,
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event,
+    },
+    queue,
+    terminal::{
+        self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
+        enable_raw_mode,
+    },
+};
+#[cfg(unix)]
+use signal_hook::{
+    consts::{SIGHUP, SIGTERM, SIGTSTP},
+    iterator::Signals,
+    low_level::{emulate_default_handler, signal_name},
+};
+#[cfg(unix)]
+use std::ffi::c_int;
+
+use super::{ColorSupport, Terminal};
+use crate::theme::Style;
+
+/// What the editor needs from the terminal it draws on and reads input
+/// from. `Terminal` sends everything through the backend of the current
+/// thread, set with `Terminal::set_backend`.
+pub trait Backend {
+    /// Prepares the terminal for the editor, like entering raw mode.
+    fn initialize(&mut self) -> io::Result<()>;
+    /// Undoes `initialize`.
+    fn terminate(&mut self) -> io::Result<()>;
+    /// Hands the terminal back to the shell until the editor is resumed.
+    fn suspend(&mut self) -> io::Result<()>;
+
+    /// Columns and rows.
+    fn size(&self) -> io::Result<(u16, u16)>;
+    /// Blanks the whole screen in the current style.
+    fn clear(&mut self) -> io::Result<()>;
+    fn move_cursor(&mut self, x: u16, y: u16) -> io::Result<()>;
+    fn show_cursor(&mut self, visible: bool) -> io::Result<()>;
+    /// Style of the text printed from now on.
+    fn set_style(&mut self, style: &Style) -> io::Result<()>;
+    /// Draws `text` at the cursor, which moves past it.
+    fn print(&mut self, text: &str) -> io::Result<()>;
+    /// Puts `text` on the clipboard of the machine the terminal runs on.
+    fn copy_to_clipboard(&mut self, text: &str) -> io::Result<()>;
+    /// Makes everything sent so far show up.
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Whether an event arrives within `timeout`.
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<bool>;
+    /// Waits for the next event.
+    fn read_event(&mut self) -> io::Result<Event>;
+}
+
+// How often a wait for input stops to look for signals.
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+// The terminal is in raw mode on the alternate screen, and has to be put
+// back if the editor panics.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The terminal the editor runs in, driven through crossterm. It draws on
+/// the alternate screen, leaving the shell's screen as it was, and puts the
+/// terminal back on a panic, `SIGTERM` or `SIGHUP`. A `SIGTSTP` suspends
+/// the editor as Ctrl+Z does in other programs.
+pub struct CrosstermBackend {
+    out: Stdout,
+    #[cfg(unix)]
+    signals: Option<Signals>,
+    // signals taken from `signals` but not dealt with yet
+    #[cfg(unix)]
+    received: Vec<c_int>,
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        CrosstermBackend {
+            out: stdout(),
+            #[cfg(unix)]
+            signals: None,
+            #[cfg(unix)]
+            received: Vec::new(),
+        }
+    }
+}
+
+impl CrosstermBackend {
+    // Deals with the signals received since the last call: an event to
+    // report after being resumed, or an error that ends the editor.
+    #[cfg(unix)]
+    fn handle_signals(&mut self) -> io::Result<Option<Event>> {
+        self.receive_signals();
+        let mut event = None;
+        for signal in std::mem::take(&mut self.received) {
+            if signal == SIGTSTP {
+                self.suspend()?;
+                // redraw everything, in case the size changed meanwhile
+                let (width, height) = self.size()?;
+                event = Some(Event::Resize(width, height));
+            } else {
+                let name = signal_name(signal).unwrap_or("a signal");
+                return Err(io::Error::other(format!("received {}", name)));
+            }
+        }
+        Ok(event)
+    }
+
+    #[cfg(not(unix))]
+    fn handle_signals(&mut self) -> io::Result<Option<Event>> {
+        Ok(None)
+    }
+
+    // Whether any signal is waiting to be dealt with.
+    #[cfg(unix)]
+    fn receive_signals(&mut self) -> bool {
+        if let Some(signals) = &mut self.signals {
+            self.received.extend(signals.pending());
+        }
+        !self.received.is_empty()
+    }
+
+    #[cfg(not(unix))]
+    fn receive_signals(&mut self) -> bool {
+        false
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn initialize(&mut self) -> io::Result<()> {
+        install_panic_hook();
+        #[cfg(unix)]
+        if self.signals.is_none() {
+            self.signals = Some(Signals::new([SIGTERM, SIGHUP, SIGTSTP])?);
+        }
+        enter(&mut self.out)
+    }
+
+    fn terminate(&mut self) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            self.signals = None;
+        }
+        leave(&mut self.out)
+    }
+
+    fn suspend(&mut self) -> io::Result<()> {
+        leave(&mut self.out)?;
+        // stop for real, as if the signal had no handler, until SIGCONT
+        #[cfg(unix)]
+        emulate_default_handler(SIGTSTP)?;
+        enter(&mut self.out)
+    }
+
+    fn size(&self) -> io::Result<(u16, u16)> {
+        terminal::size()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        queue!(self.out, Clear(ClearType::All))
+    }
+
+    fn move_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        queue!(self.out, cursor::MoveTo(x, y))
+    }
+
+    fn show_cursor(&mut self, visible: bool) -> io::Result<()> {
+        if visible {
+            queue!(self.out, cursor::Show)
+        } else {
+            queue!(self.out, cursor::Hide)
+        }
+    }
+
+    // One SGR sequence, with the colors brought down to what the terminal
+    // supports.
+    fn set_style(&mut self, style: &Style) -> io::Result<()> {
+        let support = Terminal::color_support();
+        write!(self.out, "{}", select_graphic_rendition(style, support))
+    }
+
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        write!(self.out, "{}", text)
+    }
+
+    // An OSC 52 escape sequence, which terminals without support simply
+    // ignore.
+    fn copy_to_clipboard(&mut self, text: &str) -> io::Result<()> {
+        write!(self.out, "\x1b]52;c;{}\x07", base64(text.as_bytes()))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    // Pending signals count as an event, for `read_event` to deal with.
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<bool> {
+        if self.receive_signals() {
+            return Ok(true);
+        }
+        event::poll(timeout)
+    }
+
+    fn read_event(&mut self) -> io::Result<Event> {
+        loop {
+            if let Some(event) = self.handle_signals()? {
+                return Ok(event);
+            }
+            if event::poll(SIGNAL_INTERVAL)? {
+                return event::read();
+            }
+        }
+    }
+}
+
+fn enter(out: &mut impl Write) -> io::Result<()> {
+    enable_raw_mode()?;
+    ACTIVE.store(true, Ordering::SeqCst);
+    queue!(
+        out,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
+    out.flush()
+}
+
+fn leave(out: &mut impl Write) -> io::Result<()> {
+    ACTIVE.store(false, Ordering::SeqCst);
+    write!(out, "\x1b[0m")?;
+    queue!(
+        out,
+        DisableBracketedPaste,
+        DisableMouseCapture,
+        cursor::Show,
+        LeaveAlternateScreen
+    )?;
+    out.flush()?;
+    disable_raw_mode()
+}
+
+// Puts the terminal back before the panic message is printed, so that it
+// shows up on the shell's screen and the shell is usable afterwards.
+fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+
+    INSTALLED.call_once(|| {
+        let report = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if ACTIVE.load(Ordering::SeqCst) {
+                let _ = leave(&mut stdout());
+            }
+            report(info);
+        }));
+    });
+}
+
+fn select_graphic_rendition(style: &Style, support: ColorSupport) -> String {
+    let mut parameters = vec!["0".to_owned()];
+    parameters.extend(style.fg.map(|color| support.sgr(color, false)));
+    parameters.extend(style.bg.map(|color| support.sgr(color, true)));
+
+    let attributes = [
+        (style.bold, "1"),
+        (style.italic, "3"),
+        (style.underline, "4"),
+        (style.reverse, "7"),
+    ];
+    for (_, code) in attributes.into_iter().filter(|(on, _)| *on) {
+        parameters.push(code.to_owned());
+    }
+
+    parameters.retain(|parameter| !parameter.is_empty());
+    format!("\x1b[{}m", parameters.join(";"))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
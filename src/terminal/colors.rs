@@ -0,0 +1,232 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crossterm::style::Color;
+
+// The 16 basic colors in the order of their ANSI numbers, with the RGB
+// values xterm gives them.
+const BASIC_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// Channel values of the 6x6x6 color cube in the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    Monochrome,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Support of the terminal we run in. `NO_COLOR` turns colors off and
+    /// `EDITRA_COLORS` (`truecolor`, `256`, `16` or `none`) overrides the
+    /// detection from `COLORTERM`, terminfo and `TERM`.
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorSupport::Monochrome;
+        }
+        if let Ok(name) = env::var("EDITRA_COLORS")
+            && let Some(support) = Self::from_name(&name)
+        {
+            return support;
+        }
+        if matches!(env::var("COLORTERM").as_deref(), Ok("truecolor" | "24bit")) {
+            return ColorSupport::TrueColor;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        if term == "dumb" {
+            return ColorSupport::Monochrome;
+        }
+        if let Some(colors) = terminfo_colors(&term) {
+            return Self::from_count(colors);
+        }
+        if term.ends_with("-direct") {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "truecolor" | "24bit" => Some(ColorSupport::TrueColor),
+            "256" => Some(ColorSupport::Ansi256),
+            "16" => Some(ColorSupport::Ansi16),
+            "none" | "0" => Some(ColorSupport::Monochrome),
+            _ => None,
+        }
+    }
+
+    fn from_count(colors: u32) -> Self {
+        match colors {
+            0x100_0000.. => ColorSupport::TrueColor,
+            256.. => ColorSupport::Ansi256,
+            8.. => ColorSupport::Ansi16,
+            _ => ColorSupport::Monochrome,
+        }
+    }
+
+    /// SGR parameters selecting `color` as the foreground, or as the
+    /// background with `background`, replaced by the nearest color the
+    /// terminal has. Empty for the default color or when colors are off.
+    pub fn sgr(self, color: Color, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        let color = match (self, color) {
+            (_, Color::Reset) | (ColorSupport::Monochrome, _) => return String::new(),
+            (ColorSupport::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(nearest_palette_color((r, g, b)))
+            }
+            (ColorSupport::Ansi16, Color::Rgb { r, g, b }) => nearest_basic_color((r, g, b)),
+            (ColorSupport::Ansi16, Color::AnsiValue(index)) => {
+                nearest_basic_color(palette_rgb(index))
+            }
+            _ => color,
+        };
+
+        match color {
+            Color::Rgb { r, g, b } => format!("{};2;{};{};{}", base + 8, r, g, b),
+            Color::AnsiValue(index) => format!("{};5;{}", base + 8, index),
+            _ => match basic_index(color) {
+                Some(index @ 0..8) => (base + index).to_string(),
+                // the bright colors have codes of their own
+                Some(index) => (base + 60 + index - 8).to_string(),
+                None => String::new(),
+            },
+        }
+    }
+}
+
+fn basic_index(color: Color) -> Option<u8> {
+    BASIC_COLORS
+        .iter()
+        .position(|&(basic, _)| basic == color)
+        .map(|index| index as u8)
+}
+
+// RGB value of a color of the 256-color palette.
+fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..16 => BASIC_COLORS[index as usize].1,
+        16..232 => {
+            let cube = index - 16;
+            (
+                CUBE_LEVELS[(cube / 36) as usize],
+                CUBE_LEVELS[(cube / 6 % 6) as usize],
+                CUBE_LEVELS[(cube % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + 10 * (index - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+fn nearest_basic_color(rgb: (u8, u8, u8)) -> Color {
+    BASIC_COLORS
+        .iter()
+        .min_by_key(|(_, basic)| distance(rgb, *basic))
+        .map_or(Color::Reset, |&(color, _)| color)
+}
+
+// Nearest entry of the color cube or the gray ramp of the 256-color
+// palette. The first 16 entries are left out, as terminals differ on them.
+fn nearest_palette_color(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|&index| distance(rgb, palette_rgb(index)))
+        .unwrap_or(16)
+}
+
+// The `colors` capability of the compiled terminfo entry for `term`.
+fn terminfo_colors(term: &str) -> Option<u32> {
+    let first = term.chars().next()?;
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    dirs.extend(env::var_os("TERMINFO").map(PathBuf::from));
+    dirs.extend(env::var_os("HOME").map(|home| Path::new(&home).join(".terminfo")));
+    if let Ok(list) = env::var("TERMINFO_DIRS") {
+        dirs.extend(
+            list.split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        );
+    }
+    dirs.extend(
+        [
+            "/etc/terminfo",
+            "/lib/terminfo",
+            "/usr/share/terminfo",
+            "/usr/lib/terminfo",
+        ]
+        .map(PathBuf::from),
+    );
+
+    // entries live under their first letter, or its hex code on macOS
+    dirs.iter()
+        .flat_map(|dir| {
+            [first.to_string(), format!("{:x}", first as u32)].map(|sub| dir.join(sub).join(term))
+        })
+        .find_map(|path| fs::read(path).ok())
+        .and_then(|entry| parse_terminfo_colors(&entry))
+}
+
+// Reads `colors` from a compiled terminfo entry, as laid out in term(5).
+// An entry without the capability has no colors.
+fn parse_terminfo_colors(entry: &[u8]) -> Option<u32> {
+    // position of `colors` among the numeric capabilities
+    const COLORS: usize = 13;
+
+    let short = |at: usize| Some(u16::from_le_bytes([*entry.get(at)?, *entry.get(at + 1)?]));
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let names_size = short(2)? as usize;
+    let bools = short(4)? as usize;
+    let numbers = short(6)? as usize;
+    if numbers <= COLORS {
+        return Some(0);
+    }
+
+    // the numbers start on an even byte after the header, names and flags
+    let mut at = 12 + names_size + bools;
+    at += at % 2;
+    at += COLORS * number_size;
+    let bytes = entry.get(at..at + number_size)?;
+    let colors = match *bytes {
+        [a, b] => i16::from_le_bytes([a, b]) as i32,
+        [a, b, c, d] => i32::from_le_bytes([a, b, c, d]),
+        _ => return None,
+    };
+    // negative values mark a missing capability
+    Some(u32::try_from(colors).unwrap_or(0))
+}
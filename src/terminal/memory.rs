@@ -0,0 +1,179 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, ErrorKind},
+    rc::Rc,
+    time::Duration,
+};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+use super::{Backend, screen::Screen};
+use crate::theme::Style;
+
+/// A terminal that only exists in memory, for driving the editor without
+/// one. Events are queued up front and what gets drawn can be read back
+/// cell by cell. Clones share the same terminal, so one can be handed to
+/// `Terminal::set_backend` and another kept to look at the results.
+#[derive(Clone)]
+pub struct MemoryBackend {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    width: u16,
+    height: u16,
+    screen: Screen,
+    cursor_visible: bool,
+    clipboard: Option<String>,
+    events: VecDeque<Event>,
+}
+
+impl MemoryBackend {
+    pub fn new(width: u16, height: u16) -> Self {
+        let mut screen = Screen::default();
+        screen.resize(width as usize, height as usize);
+        let state = State {
+            width,
+            height,
+            screen,
+            cursor_visible: true,
+            clipboard: None,
+            events: VecDeque::new(),
+        };
+        MemoryBackend {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Queues `event` to be read after those already queued.
+    pub fn push_event(&self, event: Event) {
+        self.state.borrow_mut().events.push_back(event);
+    }
+
+    pub fn push_key(&self, code: KeyCode, modifiers: KeyModifiers) {
+        self.push_event(Event::Key(KeyEvent::new(code, modifiers)));
+    }
+
+    /// Queues a key press for each character of `text`.
+    pub fn push_text(&self, text: &str) {
+        for c in text.chars() {
+            self.push_key(KeyCode::Char(c), KeyModifiers::NONE);
+        }
+    }
+
+    /// Changes the size of the terminal and queues the resize event.
+    pub fn resize(&self, width: u16, height: u16) {
+        let mut state = self.state.borrow_mut();
+        state.width = width;
+        state.height = height;
+        state.screen.resize(width as usize, height as usize);
+        state.events.push_back(Event::Resize(width, height));
+    }
+
+    /// Grapheme and style of the cell at `x`, `y`. The cells covered by the
+    /// right part of a wide grapheme hold an empty string.
+    pub fn cell(&self, x: u16, y: u16) -> Option<(String, Style)> {
+        let state = self.state.borrow();
+        let (symbol, style) = state.screen.cell(x as usize, y as usize)?;
+        Some((symbol.to_owned(), style))
+    }
+
+    /// Text of row `y`, without trailing blanks.
+    pub fn line(&self, y: u16) -> String {
+        let state = self.state.borrow();
+        let line: String = (0..state.width as usize)
+            .filter_map(|x| state.screen.cell(x, y as usize))
+            .map(|(symbol, _)| symbol)
+            .collect();
+        line.trim_end().to_owned()
+    }
+
+    /// Every row, as `line` gives them.
+    pub fn lines(&self) -> Vec<String> {
+        let height = self.state.borrow().height;
+        (0..height).map(|y| self.line(y)).collect()
+    }
+
+    /// Where the cursor is, when it is shown.
+    pub fn cursor(&self) -> Option<(u16, u16)> {
+        let state = self.state.borrow();
+        let (x, y) = state.screen.cursor();
+        state.cursor_visible.then_some((x as u16, y as u16))
+    }
+
+    /// What was last copied to the host clipboard.
+    pub fn clipboard(&self) -> Option<String> {
+        self.state.borrow().clipboard.clone()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn initialize(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn terminate(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn suspend(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<(u16, u16)> {
+        let state = self.state.borrow();
+        Ok((state.width, state.height))
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.state.borrow_mut().screen.clear();
+        Ok(())
+    }
+
+    fn move_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.state
+            .borrow_mut()
+            .screen
+            .move_to(x as usize, y as usize);
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, visible: bool) -> io::Result<()> {
+        self.state.borrow_mut().cursor_visible = visible;
+        Ok(())
+    }
+
+    fn set_style(&mut self, style: &Style) -> io::Result<()> {
+        self.state.borrow_mut().screen.set_style(*style);
+        Ok(())
+    }
+
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        self.state.borrow_mut().screen.print(text);
+        Ok(())
+    }
+
+    fn copy_to_clipboard(&mut self, text: &str) -> io::Result<()> {
+        self.state.borrow_mut().clipboard = Some(text.to_owned());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn poll_event(&mut self, _timeout: Duration) -> io::Result<bool> {
+        Ok(!self.state.borrow().events.is_empty())
+    }
+
+    // Running out of events ends the editor's loop with an error rather
+    // than waiting forever.
+    fn read_event(&mut self) -> io::Result<Event> {
+        self.state
+            .borrow_mut()
+            .events
+            .pop_front()
+            .ok_or_else(|| io::Error::new(ErrorKind::UnexpectedEof, "no more events"))
+    }
+}
@@ -0,0 +1,274 @@
+use std::io;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::Backend;
+use crate::theme::Style;
+
+// Unchanged cells `flush` draws again rather than jumping over, as a cursor
+// move takes at least six bytes.
+const SHORT_GAP: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cell {
+    // grapheme shown in the cell, empty in the cells covered by the right
+    // part of a wide one
+    symbol: String,
+    style: Style,
+}
+
+impl Cell {
+    fn blank(style: Style) -> Self {
+        Cell {
+            symbol: " ".to_owned(),
+            style,
+        }
+    }
+
+    fn is_continuation(&self) -> bool {
+        self.symbol.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    fn new(width: usize, height: usize) -> Self {
+        Frame {
+            width,
+            height,
+            cells: vec![Cell::blank(Style::default()); width * height],
+        }
+    }
+
+    fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    fn row_mut(&mut self, y: usize) -> &mut [Cell] {
+        &mut self.cells[y * self.width..(y + 1) * self.width]
+    }
+}
+
+/// The screen as a grid of cells. Drawing goes to a back frame, and
+/// `flush` sends the terminal only the cells that differ from the frame it
+/// shows.
+#[derive(Debug, Default)]
+pub(super) struct Screen {
+    back: Frame,
+    // what the terminal shows, unknown until the first flush
+    front: Option<Frame>,
+    cursor: (usize, usize),
+    style: Style,
+    cursor_visible: bool,
+}
+
+impl Screen {
+    /// Makes the back frame `width` by `height`, keeping what fits.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if (width, height) == (self.back.width, self.back.height) {
+            return;
+        }
+
+        let mut frame = Frame::new(width, height);
+        let columns = width.min(self.back.width);
+        for y in 0..height.min(self.back.height) {
+            let old = self.back.row(y);
+            let row = frame.row_mut(y);
+            row[..columns].clone_from_slice(&old[..columns]);
+            // a wide grapheme cut in two by the new edge
+            if old.get(columns).is_some_and(Cell::is_continuation)
+                && let Some(start) = row.iter().rposition(|cell| !cell.is_continuation())
+            {
+                for cell in &mut row[start..] {
+                    *cell = Cell::blank(cell.style);
+                }
+            }
+        }
+        self.back = frame;
+    }
+
+    pub fn move_to(&mut self, x: usize, y: usize) {
+        self.cursor = (x, y);
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        self.cursor
+    }
+
+    /// Grapheme and style of a cell of the back frame.
+    pub fn cell(&self, x: usize, y: usize) -> Option<(&str, Style)> {
+        if x >= self.back.width {
+            return None;
+        }
+        let cell = self.back.cells.get(y * self.back.width + x)?;
+        Some((&cell.symbol, cell.style))
+    }
+
+    /// Forgets what the terminal shows, so the next flush redraws it all.
+    pub fn invalidate(&mut self) {
+        self.front = None;
+    }
+
+    /// Blanks the whole frame in the current style.
+    pub fn clear(&mut self) {
+        self.back.cells.fill(Cell::blank(self.style));
+    }
+
+    /// Blanks the row under the cursor in the current style.
+    pub fn clear_line(&mut self) {
+        let (_, y) = self.cursor;
+        if y < self.back.height {
+            let style = self.style;
+            self.back.row_mut(y).fill(Cell::blank(style));
+        }
+    }
+
+    /// Draws `text` at the cursor and moves the cursor past it. Text past
+    /// the right edge is cut off, and control characters show as blanks.
+    pub fn print(&mut self, text: &str) {
+        for grapheme in text.graphemes(true) {
+            let width = grapheme.width();
+            let control = grapheme.chars().any(char::is_control);
+            let (x, y) = self.cursor;
+            if y >= self.back.height {
+                return;
+            }
+            let drawn = x.min(self.back.width);
+
+            if width == 0 {
+                // combining marks and the like join the grapheme before them
+                if !control
+                    && let Some(cell) = self.back.row_mut(y)[..drawn]
+                        .iter_mut()
+                        .rev()
+                        .find(|cell| !cell.is_continuation())
+                {
+                    cell.symbol.push_str(grapheme);
+                }
+                continue;
+            }
+
+            if x + width <= self.back.width {
+                if control {
+                    for column in x..x + width {
+                        self.put(column, y, " ", 1);
+                    }
+                } else {
+                    self.put(x, y, grapheme, width);
+                }
+            }
+            self.cursor.0 += width;
+        }
+    }
+
+    fn put(&mut self, x: usize, y: usize, grapheme: &str, width: usize) {
+        let style = self.style;
+        let row = self.back.row_mut(y);
+
+        // a wide grapheme that is partly drawn over goes away entirely
+        if row[x].is_continuation() {
+            let start = row[..x].iter().rposition(|cell| !cell.is_continuation());
+            for cell in &mut row[start.unwrap_or(x)..x] {
+                *cell = Cell::blank(cell.style);
+            }
+        }
+        for cell in row[x + width..]
+            .iter_mut()
+            .take_while(|cell| cell.is_continuation())
+        {
+            *cell = Cell::blank(cell.style);
+        }
+
+        row[x] = Cell {
+            symbol: grapheme.to_owned(),
+            style,
+        };
+        for cell in &mut row[x + 1..x + width] {
+            *cell = Cell {
+                symbol: String::new(),
+                style,
+            };
+        }
+    }
+
+    /// Sends the changes since the last flush to `backend`, moving the cursor
+    /// only to skip unchanged cells and switching styles only when they
+    /// differ. Everything is redrawn the first time and after a resize.
+    pub fn flush(&mut self, backend: &mut dyn Backend) -> io::Result<()> {
+        backend.show_cursor(false)?;
+
+        let back = &self.back;
+        let front = self
+            .front
+            .as_ref()
+            .filter(|front| (front.width, front.height) == (back.width, back.height));
+        if front.is_none() {
+            backend.set_style(&Style::default())?;
+            backend.clear()?;
+        }
+
+        // where the terminal's cursor is and which style it draws in, once
+        // known
+        let mut position = None;
+        let mut pen = None;
+        for y in 0..back.height {
+            let row = back.row(y);
+            for (x, cell) in row.iter().enumerate() {
+                if cell.is_continuation() || front.is_some_and(|front| front.row(y)[x] == *cell) {
+                    continue;
+                }
+
+                match position {
+                    Some(at) if at == (x, y) => {}
+                    // a short run of unchanged cells in the same style is
+                    // cheaper to draw again than to move over
+                    Some((from, row_y))
+                        if row_y == y
+                            && from < x
+                            && x - from <= SHORT_GAP
+                            && row[from..x].iter().all(|cell| pen == Some(cell.style)) =>
+                    {
+                        for cell in &row[from..x] {
+                            backend.print(&cell.symbol)?;
+                        }
+                    }
+                    _ => backend.move_cursor(x as u16, y as u16)?,
+                }
+                if pen != Some(cell.style) {
+                    backend.set_style(&cell.style)?;
+                    pen = Some(cell.style);
+                }
+                backend.print(&cell.symbol)?;
+
+                let width = 1 + row[x + 1..]
+                    .iter()
+                    .take_while(|cell| cell.is_continuation())
+                    .count();
+                position = Some((x + width, y));
+            }
+        }
+
+        if self.cursor_visible {
+            let (x, y) = self.cursor;
+            backend.move_cursor(x as u16, y as u16)?;
+            backend.show_cursor(true)?;
+        }
+        self.front = Some(self.back.clone());
+        Ok(())
+    }
+}
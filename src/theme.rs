@@ -0,0 +1,191 @@
+use std::{collections::HashMap, env, fs, sync::OnceLock};
+
+use crossterm::style::Color;
+use serde::{Deserialize, Deserializer, de};
+
+use crate::{
+    config,
+    highlighter::HighlightType,
+    terminal::{ColorSupport, Terminal},
+};
+
+// Bundled themes, in the same format as user files. The first one is the
+// default.
+const BUILTIN: &[(&str, &str)] = &[
+    ("dark.toml", include_str!("../themes/dark.toml")),
+    ("light.toml", include_str!("../themes/light.toml")),
+    ("monochrome.toml", include_str!("../themes/monochrome.toml")),
+];
+
+// Theme for terminals without colors, or with `NO_COLOR` set.
+const MONOCHROME: &str = "Monochrome";
+
+/// Colors and attributes for one kind of text. Unset colors show the ones
+/// of the style underneath, and in the end the terminal's own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    #[serde(deserialize_with = "color")]
+    pub fg: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    /// This style with what `over` sets laid on top.
+    pub fn patch(self, over: Style) -> Style {
+        Style {
+            fg: over.fg.or(self.fg),
+            bg: over.bg.or(self.bg),
+            bold: self.bold || over.bold,
+            italic: self.italic || over.italic,
+            underline: self.underline || over.underline,
+            reverse: self.reverse || over.reverse,
+        }
+    }
+}
+
+/// Colors for the syntax categories and the parts of the screen.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub name: String,
+    /// Plain buffer text and the prompt. Everything else is drawn on top.
+    pub text: Style,
+    pub status_bar: Style,
+    pub selection: Style,
+    pub search_match: Style,
+    /// The `~` markers on rows past the end of the file.
+    pub gutter: Style,
+    /// Line numbers beside the text, which the editor does not show yet.
+    pub line_number: Style,
+    pub syntax: HashMap<HighlightType, Style>,
+}
+
+impl Theme {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let theme: Theme = toml::from_str(source).map_err(|err| err.message().to_owned())?;
+        if theme.name.is_empty() {
+            return Err("missing `name`".to_owned());
+        }
+        Ok(theme)
+    }
+
+    /// Style of text highlighted as `highlight_type`.
+    pub fn syntax_style(&self, highlight_type: HighlightType) -> Style {
+        self.syntax
+            .get(&highlight_type)
+            .map_or(self.text, |style| self.text.patch(*style))
+    }
+}
+
+/// All known themes: the bundled ones, replaced or extended by the `*.toml`
+/// files in the `themes` configuration directory.
+pub struct Themes {
+    pub themes: Vec<Theme>,
+    /// Theme files that could not be loaded, with the reason.
+    pub errors: Vec<String>,
+}
+
+static THEMES: OnceLock<Themes> = OnceLock::new();
+
+impl Themes {
+    pub fn get() -> &'static Themes {
+        THEMES.get_or_init(Self::load)
+    }
+
+    fn load() -> Self {
+        let mut themes = Themes {
+            themes: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for (origin, source) in BUILTIN {
+            themes.add(origin, source);
+        }
+        for path in config::config_files("themes", "toml") {
+            match fs::read_to_string(&path) {
+                Ok(source) => themes.add(&path.display().to_string(), &source),
+                Err(err) => themes.errors.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+
+        themes
+    }
+
+    // A theme with the name of a known one replaces it.
+    fn add(&mut self, origin: &str, source: &str) {
+        let theme = match Theme::parse(source) {
+            Ok(theme) => theme,
+            Err(err) => return self.errors.push(format!("{}: {}", origin, err)),
+        };
+
+        match self.find_index(&theme.name) {
+            Some(index) => self.themes[index] = theme,
+            None => self.themes.push(theme),
+        }
+    }
+
+    fn find_index(&self, name: &str) -> Option<usize> {
+        self.themes
+            .iter()
+            .position(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Theme> {
+        self.find_index(name).map(|index| &self.themes[index])
+    }
+
+    /// The theme named by `$EDITRA_THEME`, or the default one. Without
+    /// colors it is the monochrome theme, which only uses attributes.
+    pub fn initial(&self) -> &Theme {
+        let name = if Terminal::color_support() == ColorSupport::Monochrome {
+            Some(MONOCHROME.to_owned())
+        } else {
+            env::var("EDITRA_THEME").ok()
+        };
+        name.and_then(|name| self.find(&name))
+            .unwrap_or(&self.themes[0])
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.themes
+            .iter()
+            .map(|theme| theme.name.as_str())
+            .collect()
+    }
+}
+
+// A color by name (`dark_red`), as `#rrggbb`, as an ANSI palette index or
+// `reset` for the terminal's own.
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    parse_color(&name)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("unknown color `{}`", name)))
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    if name == "reset" {
+        return Some(Color::Reset);
+    }
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+        return Some(Color::Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+    if let Ok(index) = name.parse() {
+        return Some(Color::AnsiValue(index));
+    }
+    Color::try_from(name).ok()
+}
@@ -1,19 +1,37 @@
+use std::cmp::Ordering;
 use std::io::{self};
+use std::ops::Range;
 
-use crate::highlighter::{Annotation, Highlighter};
-use crossterm::event::KeyCode;
-use crossterm::style::{Attribute, ResetColor, SetAttribute, SetForegroundColor};
+use crate::highlighter::{Annotation, FileType, HighlightType, Highlighter};
+use crossterm::event::{KeyCode, MouseButton, MouseEvent, MouseEventKind};
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
-use crate::{buffer::Buffer, terminal::Terminal};
+use crate::{
+    buffer::{Buffer, Position},
+    replace::{Replace, ReplaceStage},
+    search::Search,
+    terminal::Terminal,
+    theme::{Theme, Themes},
+};
 
 const NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+// lines highlighted per idle tick past the viewport
+const IDLE_HIGHLIGHT_LINES: usize = 2000;
+// gutter mark of the rows a soft-wrapped line continues on
+const WRAP_INDICATOR: &str = "↪";
+// columns kept visible beside the cursor when scrolling sideways
+const SIDE_SCROLL_MARGIN: usize = 5;
+// columns between tab stops, as terminals have them
+const TAB_WIDTH: usize = 8;
 
 pub struct Offset {
+    // in display columns
     x: usize,
     y: usize,
+    // visual row of line `y` at the top of the screen while soft wrapping
+    row: usize,
 }
 
 pub struct CursorLocation {
@@ -27,39 +45,90 @@ pub struct View {
     pub cursor_location: CursorLocation,
     pub offset: Offset,
     pub highlighter: Highlighter,
+    pub selection_anchor: Option<Position>,
+    pub search: Search,
+    pub replace: Replace,
+    pub message: Option<String>,
+    pub theme: &'static Theme,
+    /// Long lines continue on the following rows instead of being cut off.
+    pub soft_wrap: bool,
+    /// Columns kept visible left and right of the cursor, as far as the
+    /// terminal is wide enough.
+    pub side_scroll_margin: usize,
 }
 
-impl View {
-    pub fn default() -> Self {
+#[derive(Clone, Copy, PartialEq)]
+struct CellStyle {
+    highlight_type: HighlightType,
+    selected: bool,
+    matched: bool,
+}
+
+impl Default for CellStyle {
+    fn default() -> Self {
+        CellStyle {
+            highlight_type: HighlightType::None,
+            selected: false,
+            matched: false,
+        }
+    }
+}
+
+impl Default for View {
+    fn default() -> Self {
         View {
             buffer: Buffer::default(),
             need_redraw: true,
             cursor_location: CursorLocation { x: 0, y: 0 },
-            offset: Offset { x: 0, y: 0 },
-            highlighter: Highlighter::new(crate::highlighter::FileType::PlainText),
+            offset: Offset { x: 0, y: 0, row: 0 },
+            highlighter: Highlighter::new(FileType::default()),
+            selection_anchor: None,
+            search: Search::default(),
+            replace: Replace::default(),
+            message: None,
+            theme: Themes::get().initial(),
+            soft_wrap: false,
+            side_scroll_margin: SIDE_SCROLL_MARGIN,
         }
     }
+}
 
+impl View {
     pub fn get_cursor_location(&self) -> (usize, usize) {
-        let screen_x = self.cursor_location.x.saturating_sub(self.offset.x);
+        if self.soft_wrap {
+            return self.wrapped_cursor_location();
+        }
+        let screen_x = self.cursor_column().saturating_sub(self.offset.x);
         let screen_y = self.cursor_location.y.saturating_sub(self.offset.y);
         (screen_x, screen_y)
-        // (self.cursor_location.x, self.cursor_location.y)
     }
 
-    pub fn update_cursor_location(&mut self, code: KeyCode) -> Result<(), io::Error> {
+    // Display column of the cursor in its line.
+    fn cursor_column(&self) -> usize {
+        let position = self.cursor_position();
+        self.buffer
+            .line(position.line)
+            .map_or(0, |line| display_width(&line[..position.byte]))
+    }
+
+    pub fn update_cursor_location(&mut self, code: KeyCode, extend: bool) -> Result<(), io::Error> {
+        if !extend {
+            self.selection_anchor = None;
+        } else if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.cursor_position());
+        }
+
         let mut x = self.cursor_location.x;
         let mut y = self.cursor_location.y;
         let height = Terminal::size().1 as usize;
         match code {
+            KeyCode::Up | KeyCode::Down if self.soft_wrap => {
+                (x, y) = self.row_above_or_below(code == KeyCode::Down);
+            }
             KeyCode::Up => {
                 y = y.saturating_sub(1);
 
-                if let Some(line) = self.buffer.lines.get(y) {
-                    if line.graphemes(true).count() < x {
-                        x = line.len();
-                    }
-                }
+                x = x.min(self.buffer.grapheme_count(y));
 
                 if y < self.offset.y {
                     self.offset.y = y;
@@ -68,24 +137,14 @@ impl View {
             KeyCode::Down => {
                 y += 1;
 
-                if let Some(line) = self.buffer.lines.get(y) {
-                    if line.graphemes(true).count() < x {
-                        x = line.len();
-                    }
-                }
+                x = x.min(self.buffer.grapheme_count(y));
 
                 if y >= self.offset.y + height {
                     self.offset.y += 1;
                 }
             }
             KeyCode::Left => x = x.saturating_sub(1),
-            KeyCode::Right => {
-                if let Some(line) = self.buffer.lines.get(y) {
-                    if x < line.graphemes(true).count() {
-                        x += 1;
-                    }
-                }
-            }
+            KeyCode::Right if x < self.buffer.grapheme_count(y) => x += 1,
 
             KeyCode::PageUp => {
                 y = y.saturating_sub(height);
@@ -102,212 +161,553 @@ impl View {
                 }
             }
             KeyCode::End => {
-                x = self.buffer.lines[y].len();
+                x = self.buffer.grapheme_count(y);
             }
             KeyCode::Home => x = 0,
             _ => {}
         }
 
-        self.cursor_location = CursorLocation {
-            x: x as usize,
-            y: y as usize,
-        };
+        self.cursor_location = CursorLocation { x, y };
+        self.scroll_to_cursor();
+        self.buffer.history.seal();
         self.need_redraw = true;
         Ok(())
     }
 
-    fn render_buffer(&self) -> Result<(), io::Error> {
-        Terminal::move_cursor_to(0, 0)?;
-        Terminal::clear_terminal()?;
-        let height = Terminal::size().1 as usize - 1;
+    /// Switches soft wrapping on or off.
+    pub fn toggle_soft_wrap(&mut self) {
+        self.soft_wrap = !self.soft_wrap;
+        self.offset.x = 0;
+        self.offset.row = 0;
+        self.scroll_to_cursor();
+        self.message = Some(format!(
+            "Soft wrap {}",
+            if self.soft_wrap { "on" } else { "off" }
+        ));
+        self.need_redraw = true;
+    }
+
+    // Byte offsets where the visual rows of line `line_idx` start.
+    fn wrap_rows(&self, line_idx: usize) -> Vec<usize> {
         let width = Terminal::size().0 as usize;
+        self.buffer
+            .line(line_idx)
+            .map_or_else(|| vec![0], |line| wrap_rows(&line, width))
+    }
 
-        for curr_row in 0..height {
-            if let Some(line) = self.buffer.lines.get(curr_row + self.offset.y) {
-                let mut current_width = 0;
-                let mut skipped_width = 0;
+    // Visual row of the cursor within its line while soft wrapping, and its
+    // column on screen.
+    fn cursor_row_and_column(&self) -> (usize, usize) {
+        let position = self.cursor_position();
+        let Some(line) = self.buffer.line(position.line) else {
+            return (0, 0);
+        };
 
-                let mut visible = String::new();
+        let rows = wrap_rows(&line, Terminal::size().0 as usize);
+        let row = rows.partition_point(|&start| start <= position.byte) - 1;
+        let indent = if row > 0 { WRAP_INDICATOR.width() } else { 0 };
+        (row, indent + display_width(&line[rows[row]..position.byte]))
+    }
 
-                for g in line.graphemes(true) {
-                    let w = g.width();
+    fn wrapped_cursor_location(&self) -> (usize, usize) {
+        let (width, height) = Terminal::size();
+        let (row, column) = self.cursor_row_and_column();
 
-                    // skip until horizontal offset reached
-                    if skipped_width < self.offset.x {
-                        skipped_width += w;
-                        continue;
-                    }
+        let mut screen_y = row;
+        for line_idx in self.offset.y..self.cursor_location.y {
+            screen_y += self.wrap_rows(line_idx).len();
+            if screen_y > height as usize + self.offset.row {
+                break;
+            }
+        }
+        let screen_y = screen_y.saturating_sub(self.offset.row);
+        (column.min((width as usize).saturating_sub(1)), screen_y)
+    }
 
-                    // stop if screen full
-                    if current_width + w > width {
-                        break;
-                    }
+    // Grapheme index and line of the spot one visual row above or below the
+    // cursor, as close to its column as that row allows.
+    fn row_above_or_below(&self, down: bool) -> (usize, usize) {
+        let (row, column) = self.cursor_row_and_column();
+        let y = self.cursor_location.y;
 
-                    visible.push_str(g);
-                    current_width += w;
-                }
-                self.render_line(
-                    curr_row as u16,
-                    visible.as_str(),
-                    Some(curr_row + self.offset.y),
+        let (line_idx, row) = if down {
+            if row + 1 < self.wrap_rows(y).len() {
+                (y, row + 1)
+            } else {
+                (y + 1, 0)
+            }
+        } else if row > 0 {
+            (y, row - 1)
+        } else if y > 0 {
+            (y - 1, self.wrap_rows(y - 1).len() - 1)
+        } else {
+            return (self.cursor_location.x, y);
+        };
+
+        let Some(line) = self.buffer.line(line_idx) else {
+            return (0, line_idx);
+        };
+        let rows = wrap_rows(&line, Terminal::size().0 as usize);
+        let byte = byte_at_column(&line, &rows, row, column);
+        (self.buffer.grapheme_index(line_idx, byte), line_idx)
+    }
+
+    fn render_buffer(&self) -> Result<(), io::Error> {
+        Terminal::set_style(&self.theme.text)?;
+        Terminal::move_cursor_to(0, 0)?;
+        Terminal::clear_terminal()?;
+        let height = Terminal::size().1 as usize - 1;
+        let width = Terminal::size().0 as usize;
+        if self.soft_wrap {
+            return self.render_wrapped_buffer(height, width);
+        }
+
+        for curr_row in 0..height {
+            let line_idx = curr_row + self.offset.y;
+            if let Some(line) = self.buffer.line(line_idx) {
+                Terminal::move_cursor_to(0, curr_row as u16)?;
+                Terminal::clear_line()?;
+                self.render_line_with_highlighting(
+                    line_idx,
+                    &line,
+                    0..line.len(),
+                    self.offset.x,
+                    width,
                 )?;
             } else {
-                self.render_line(curr_row as u16, "~", None)?;
+                self.render_filler_line(curr_row as u16, "")?;
             }
         }
 
         Ok(())
     }
 
-    fn render_line(&self, row: u16, text: &str, line_idx: Option<usize>) -> Result<(), io::Error> {
-        Terminal::move_cursor_to(0, row)?;
-        Terminal::clear_line()?;
-
-        if let Some(idx) = line_idx {
-            if let Some(annotations) = self.highlighter.get_annotations(idx) {
-                return self.render_line_with_highlighting(text, annotations);
+    fn render_wrapped_buffer(&self, height: usize, width: usize) -> Result<(), io::Error> {
+        let mut line_idx = self.offset.y;
+        let mut first_row = self.offset.row;
+        let mut screen_row = 0;
+
+        while screen_row < height {
+            let Some(line) = self.buffer.line(line_idx) else {
+                self.render_filler_line(screen_row as u16, "")?;
+                screen_row += 1;
+                continue;
+            };
+
+            let rows = wrap_rows(&line, width);
+            for (row, &start) in rows.iter().enumerate().skip(first_row) {
+                if screen_row >= height {
+                    break;
+                }
+                let end = rows.get(row + 1).copied().unwrap_or(line.len());
+
+                Terminal::move_cursor_to(0, screen_row as u16)?;
+                Terminal::clear_line()?;
+                let mut available = width;
+                if row > 0 {
+                    Terminal::set_style(&self.theme.text.patch(self.theme.gutter))?;
+                    Terminal::print(WRAP_INDICATOR)?;
+                    Terminal::set_style(&self.theme.text)?;
+                    available = available.saturating_sub(WRAP_INDICATOR.width());
+                }
+                self.render_line_with_highlighting(line_idx, &line, start..end, 0, available)?;
+                screen_row += 1;
             }
+
+            line_idx += 1;
+            first_row = 0;
         }
 
+        Ok(())
+    }
+
+    // A row past the end of the file: a `~` marker, then `text`.
+    fn render_filler_line(&self, row: u16, text: &str) -> Result<(), io::Error> {
+        Terminal::move_cursor_to(0, row)?;
+        Terminal::clear_line()?;
+        Terminal::set_style(&self.theme.text.patch(self.theme.gutter))?;
+        Terminal::print("~")?;
+        Terminal::set_style(&self.theme.text)?;
         Terminal::print(text)?;
         Ok(())
     }
 
+    // Draws the graphemes of `line` within the byte range `segment`, leaving
+    // out the first `skip` columns, in at most `width` columns.
     fn render_line_with_highlighting(
         &self,
-        text: &str,
-        annotations: &[Annotation],
+        line_idx: usize,
+        line: &str,
+        segment: Range<usize>,
+        skip: usize,
+        width: usize,
     ) -> Result<(), io::Error> {
-        let mut last_end = 0;
-        let mut sorted = annotations.to_vec();
-        sorted.sort_by_key(|a| a.start);
+        let annotations: &[Annotation] = self
+            .highlighter
+            .get_annotations(line_idx)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let selected = self.selected_range(line_idx, line.len());
+        let matches = if self.search.active {
+            self.search.matches_in_line(line)
+        } else {
+            Vec::new()
+        };
 
-        for annotation in sorted {
-            if annotation.start > last_end {
-                Terminal::print(&text[last_end..annotation.start])?;
+        let mut current_width = 0;
+        let mut skipped_width = 0;
+        let mut run = String::new();
+        let mut run_style = CellStyle::default();
+
+        for (byte_idx, g) in line[segment.clone()].grapheme_indices(true) {
+            let byte_idx = segment.start + byte_idx;
+            let column = if skipped_width < skip {
+                skipped_width
+            } else {
+                skip + current_width
+            };
+            let w = grapheme_width(g, column);
+
+            // skip until horizontal offset reached
+            if skipped_width < skip {
+                skipped_width += w;
+                // a wide grapheme cut by the left edge leaves blanks
+                if skipped_width > skip {
+                    run.push_str(&" ".repeat(skipped_width - skip));
+                    current_width += skipped_width - skip;
+                }
+                continue;
             }
 
-            let color = annotation.highlight_type.to_color();
-            Terminal::queue_command(SetForegroundColor(color))?;
-            Terminal::print(&text[annotation.start..annotation.end])?;
-            Terminal::queue_command(ResetColor)?;
+            // stop if screen full
+            if current_width + w > width {
+                break;
+            }
 
-            last_end = annotation.end;
-        }
+            let style = CellStyle {
+                highlight_type: annotations
+                    .iter()
+                    .find(|a| a.start <= byte_idx && byte_idx < a.end)
+                    .map_or(HighlightType::None, |a| a.highlight_type),
+                selected: selected.as_ref().is_some_and(|r| r.contains(&byte_idx)),
+                matched: matches.iter().any(|m| m.contains(&byte_idx)),
+            };
+
+            if style != run_style {
+                self.print_styled(&run, run_style)?;
+                run.clear();
+                run_style = style;
+            }
 
-        if last_end < text.len() {
-            Terminal::print(&text[last_end..])?;
+            if g == "\t" {
+                run.push_str(&" ".repeat(w));
+            } else {
+                run.push_str(g);
+            }
+            current_width += w;
+        }
+        self.print_styled(&run, run_style)?;
+
+        // a selected line break shows up as one highlighted cell
+        if selected.is_some_and(|r| r.end > line.len())
+            && segment.end == line.len()
+            && current_width < width
+        {
+            let style = CellStyle {
+                selected: true,
+                ..CellStyle::default()
+            };
+            self.print_styled(" ", style)?;
         }
 
         Ok(())
     }
 
-    pub fn insert_char_to_line(&mut self, c: char) -> Result<(), io::Error> {
-        if self.cursor_location.y >= self.buffer.lines.len() {
-            self.buffer.lines.push(String::new());
+    fn print_styled(&self, text: &str, style: CellStyle) -> Result<(), io::Error> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        if style == CellStyle::default() {
+            return Terminal::print(text);
+        }
+
+        let mut cell_style = self.theme.syntax_style(style.highlight_type);
+        if style.matched {
+            cell_style = cell_style.patch(self.theme.search_match);
+        }
+        if style.selected {
+            cell_style = cell_style.patch(self.theme.selection);
         }
 
-        let line = self.buffer.lines.get_mut(self.cursor_location.y).unwrap();
+        Terminal::set_style(&cell_style)?;
+        Terminal::print(text)?;
+        Terminal::set_style(&self.theme.text)
+    }
 
-        let byte_index = line
-            .grapheme_indices(true)
-            .nth(self.cursor_location.x)
-            .map(|(i, _)| i)
-            .unwrap_or(line.len());
+    /// Ordered bounds of the current selection, if any text is selected.
+    pub fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.buffer.clamp(self.selection_anchor?);
+        let cursor = self.buffer.clamp(self.cursor_position());
 
-        line.insert(byte_index, c);
+        match anchor.cmp(&cursor) {
+            Ordering::Less => Some((anchor, cursor)),
+            Ordering::Greater => Some((cursor, anchor)),
+            Ordering::Equal => None,
+        }
+    }
 
-        self.cursor_location.x += 1;
-        self.need_redraw = true;
+    // Byte range of `line_idx` covered by the selection. The range runs one
+    // past the line end when the line break itself is selected.
+    fn selected_range(&self, line_idx: usize, line_len: usize) -> Option<Range<usize>> {
+        let (start, end) = self.selection()?;
+        if line_idx < start.line || line_idx > end.line {
+            return None;
+        }
 
-        Ok(())
+        let from = if line_idx == start.line {
+            start.byte
+        } else {
+            0
+        };
+        let to = if line_idx == end.line {
+            end.byte
+        } else {
+            line_len + 1
+        };
+        Some(from..to)
     }
 
-    pub fn delete_char(&mut self) -> Result<(), io::Error> {
-        let y = self.cursor_location.y;
-        let x = self.cursor_location.x;
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        Some(self.buffer.slice(start, end))
+    }
+
+    pub fn cut_selection(&mut self) -> Option<String> {
+        let text = self.selected_text()?;
+        self.delete_selection();
+        self.buffer.history.seal();
+        Some(text)
+    }
 
-        if y >= self.buffer.lines.len() {
+    /// Inserts `text` as its own undo step, replacing any selection.
+    pub fn paste(&mut self, text: &str) -> Result<(), io::Error> {
+        if text.is_empty() {
             return Ok(());
         }
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
 
-        let grapheme_count = self.buffer.lines[y].graphemes(true).count();
+        self.buffer.history.seal();
+        self.insert_text(&text)?;
+        self.buffer.history.seal();
 
-        // CASE: merge next line
-        if x >= grapheme_count {
-            if y + 1 >= self.buffer.lines.len() {
-                return Ok(()); // end of document
+        Ok(())
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.selection_anchor = None;
+
+        let Some((start, end)) = selection else {
+            return false;
+        };
+
+        let cursor = self.cursor_position();
+        self.buffer.delete(start, end, cursor);
+
+        self.set_cursor_position(start);
+        self.need_redraw = true;
+
+        true
+    }
+
+    pub fn handle_mouse(&mut self, event: MouseEvent) -> Result<(), io::Error> {
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(position) = self.screen_to_position(event.column, event.row) {
+                    self.set_cursor_position(position);
+                    self.selection_anchor = Some(position);
+                    self.buffer.history.seal();
+                    self.need_redraw = true;
+                }
             }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some(position) = self.screen_to_position(event.column, event.row) {
+                    self.set_cursor_position(position);
+                    self.need_redraw = true;
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) if self.selection().is_none() => {
+                self.selection_anchor = None;
+            }
+            _ => {}
+        }
 
-            // remove next line FIRST (no borrow active)
-            let next_line = self.buffer.lines.remove(y + 1);
+        Ok(())
+    }
 
-            // now borrow current line
-            self.buffer.lines[y].push_str(&next_line);
+    fn screen_to_position(&self, column: u16, row: u16) -> Option<Position> {
+        let height = Terminal::size().1 as usize - 1;
+        if row as usize >= height || self.buffer.is_empty() {
+            return None;
+        }
+        if self.soft_wrap {
+            return Some(self.wrapped_screen_to_position(column as usize, row as usize));
+        }
 
-            self.need_redraw = true;
-            return Ok(());
+        let line_idx = (row as usize + self.offset.y).min(self.buffer.line_count() - 1);
+        let line = self.buffer.line(line_idx)?;
+        let target = column as usize + self.offset.x;
+
+        let mut current_width = 0;
+        for (byte_idx, g) in line.grapheme_indices(true) {
+            current_width += grapheme_width(g, current_width);
+            if current_width > target {
+                return Some(Position {
+                    line: line_idx,
+                    byte: byte_idx,
+                });
+            }
         }
 
-        // normal delete
-        let line = &mut self.buffer.lines[y];
+        Some(Position {
+            line: line_idx,
+            byte: line.len(),
+        })
+    }
 
-        let start = line.grapheme_indices(true).nth(x).map(|(i, _)| i);
+    fn wrapped_screen_to_position(&self, column: usize, row: usize) -> Position {
+        let width = Terminal::size().0 as usize;
+        let mut line_idx = self.offset.y;
+        let mut row = self.offset.row + row;
+
+        while let Some(line) = self.buffer.line(line_idx) {
+            let rows = wrap_rows(&line, width);
+            if row < rows.len() {
+                return Position {
+                    line: line_idx,
+                    byte: byte_at_column(&line, &rows, row, column),
+                };
+            }
+            row -= rows.len();
+            line_idx += 1;
+        }
 
-        let end = line
-            .grapheme_indices(true)
-            .nth(x + 1)
-            .map(|(i, _)| i)
-            .unwrap_or(line.len());
+        // below the last line
+        let last = self.buffer.line_count() - 1;
+        Position {
+            line: last,
+            byte: self.buffer.line_len(last),
+        }
+    }
 
-        if let Some(start_index) = start {
-            line.replace_range(start_index..end, "");
+    pub fn insert_char_to_line(&mut self, c: char) -> Result<(), io::Error> {
+        self.insert_text(c.encode_utf8(&mut [0; 4]))
+    }
+
+    pub fn insert_text(&mut self, text: &str) -> Result<(), io::Error> {
+        // typing over a selection replaces it in a single undo step
+        if self.selection().is_some() {
+            self.buffer.history.begin_group();
+            self.delete_selection();
+            let result = self.insert_text(text);
+            self.buffer.history.end_group();
+            return result;
         }
+        self.selection_anchor = None;
+
+        let y = self.cursor_location.y;
+        let line_count = self.buffer.line_count();
+
+        // A cursor below the document gets padded with empty lines first.
+        let (at, padding) = if y < line_count {
+            (self.cursor_position(), 0)
+        } else {
+            let last = line_count - 1;
+            let at = Position {
+                line: last,
+                byte: self.buffer.line_len(last),
+            };
+            (at, y - last)
+        };
+
+        let text = "\n".repeat(padding) + text;
+        let end = self.buffer.insert(at, &text);
 
+        self.set_cursor_position(end);
         self.need_redraw = true;
 
         Ok(())
     }
 
-    pub fn backspace_char(&mut self) -> Result<(), io::Error> {
+    pub fn delete_char(&mut self) -> Result<(), io::Error> {
+        if self.delete_selection() {
+            return Ok(());
+        }
+
         let y = self.cursor_location.y;
         let x = self.cursor_location.x;
 
-        if y == 0 && x == 0 {
+        if y >= self.buffer.line_count() {
             return Ok(());
         }
 
-        if x == 0 {
-            let current_line = self.buffer.lines.remove(y);
-
-            let prev_line = self.buffer.lines.get_mut(y - 1).unwrap();
+        let start = self.cursor_position();
 
-            let prev_len = prev_line.graphemes(true).count();
-
-            prev_line.push_str(&current_line);
+        // CASE: merge next line
+        let end = if x >= self.buffer.grapheme_count(y) {
+            if y + 1 >= self.buffer.line_count() {
+                return Ok(()); // end of document
+            }
+            Position {
+                line: y + 1,
+                byte: 0,
+            }
+        } else {
+            Position {
+                line: y,
+                byte: self.buffer.byte_index(y, x + 1),
+            }
+        };
 
-            self.cursor_location.y -= 1;
-            self.cursor_location.x = prev_len;
+        self.buffer.delete(start, end, start);
 
-            self.need_redraw = true;
-            self.buffer.modified = true;
+        self.set_cursor_position(start);
+        self.need_redraw = true;
 
-            self.highlighter.invalidate_from(y - 1, &self.buffer.lines);
+        Ok(())
+    }
 
+    pub fn backspace_char(&mut self) -> Result<(), io::Error> {
+        if self.delete_selection() {
             return Ok(());
         }
 
-        if y >= self.buffer.lines.len() {
-            return Ok(());
-        }
+        let y = self.cursor_location.y;
+        let x = self.cursor_location.x;
 
-        let line_len = self.buffer.lines[y].graphemes(true).count();
-        if x > line_len {
+        if (y == 0 && x == 0) || y >= self.buffer.line_count() {
             return Ok(());
         }
 
-        self.cursor_location.x -= 1;
-        self.delete_char()?;
+        let end = self.cursor_position();
+
+        // CASE: join with previous line
+        let start = if x == 0 {
+            Position {
+                line: y - 1,
+                byte: self.buffer.line_len(y - 1),
+            }
+        } else {
+            Position {
+                line: y,
+                byte: self.buffer.byte_index(y, x - 1),
+            }
+        };
+
+        self.buffer.delete(start, end, end);
+
+        self.set_cursor_position(start);
+        self.need_redraw = true;
 
         Ok(())
     }
@@ -317,45 +717,109 @@ impl View {
     }
 
     pub fn insert_newline(&mut self) -> Result<(), io::Error> {
-        let y = self.cursor_location.y;
-        let x = self.cursor_location.x;
+        self.insert_text("\n")
+    }
 
-        // Case: cursor below document
-        if y >= self.buffer.lines.len() {
-            self.buffer.lines.push(String::new());
-            self.cursor_location.y += 1;
-            self.cursor_location.x = 0;
-            return Ok(());
+    pub fn undo(&mut self) -> Result<(), io::Error> {
+        if let Some(cursor) = self.buffer.undo() {
+            self.restore_after_history(cursor);
         }
+        Ok(())
+    }
 
-        let line = self.buffer.lines.get_mut(y).unwrap();
+    pub fn redo(&mut self) -> Result<(), io::Error> {
+        if let Some(cursor) = self.buffer.redo() {
+            self.restore_after_history(cursor);
+        }
+        Ok(())
+    }
 
-        let byte_index = line
-            .grapheme_indices(true)
-            .nth(x)
-            .map(|(i, _)| i)
-            .unwrap_or(line.len());
+    fn restore_after_history(&mut self, cursor: Position) {
+        self.selection_anchor = None;
+        self.set_cursor_position(cursor);
+        self.need_redraw = true;
+    }
 
-        // split line
-        let new_line = line.split_off(byte_index);
+    fn cursor_position(&self) -> Position {
+        let y = self.cursor_location.y;
+        Position {
+            line: y,
+            byte: self.buffer.byte_index(y, self.cursor_location.x),
+        }
+    }
 
-        // insert new line below
-        self.buffer.lines.insert(y + 1, new_line);
+    fn set_cursor_position(&mut self, position: Position) {
+        self.cursor_location = CursorLocation {
+            x: self.buffer.grapheme_index(position.line, position.byte),
+            y: position.line,
+        };
+        self.scroll_to_cursor();
+    }
 
-        // move cursor
-        self.cursor_location.y += 1;
-        self.cursor_location.x = 0;
+    fn scroll_to_cursor(&mut self) {
+        let height = (Terminal::size().1 as usize).saturating_sub(1).max(1);
+        if self.soft_wrap {
+            return self.scroll_to_cursor_row(height);
+        }
+        let y = self.cursor_location.y;
 
-        self.need_redraw = true;
+        if y < self.offset.y {
+            self.offset.y = y;
+        } else if y >= self.offset.y + height {
+            self.offset.y = y - height + 1;
+        }
 
-        Ok(())
+        self.scroll_to_cursor_column();
+    }
+
+    // Scrolls sideways so the cursor stays the side scroll margin away
+    // from either edge.
+    fn scroll_to_cursor_column(&mut self) {
+        let width = (Terminal::size().0 as usize).max(1);
+        let margin = self.side_scroll_margin.min((width - 1) / 2);
+        let column = self.cursor_column();
+
+        if column < self.offset.x + margin {
+            self.offset.x = column.saturating_sub(margin);
+        } else if column + margin >= self.offset.x + width {
+            self.offset.x = column + margin + 1 - width;
+        }
+    }
+
+    // Scrolls by visual rows until the cursor's row is on screen.
+    fn scroll_to_cursor_row(&mut self, height: usize) {
+        let (row, _) = self.cursor_row_and_column();
+        let cursor = (self.cursor_location.y, row);
+        // the top line may have fewer rows since the last time
+        self.offset.row = self.offset.row.min(self.wrap_rows(self.offset.y).len() - 1);
+
+        // the lowest top that still shows the cursor on the bottom row
+        let mut top = cursor;
+        for _ in 1..height {
+            if top.1 > 0 {
+                top.1 -= 1;
+            } else if top.0 > 0 {
+                top.0 -= 1;
+                top.1 = self.wrap_rows(top.0).len() - 1;
+            } else {
+                break;
+            }
+        }
+
+        let offset = (self.offset.y, self.offset.row);
+        if cursor < offset {
+            (self.offset.y, self.offset.row) = cursor;
+        } else if offset < top {
+            (self.offset.y, self.offset.row) = top;
+        }
     }
 
     fn render_welcome_screen(&self) -> Result<(), io::Error> {
+        Terminal::set_style(&self.theme.text)?;
         Terminal::move_cursor_to(0, 0)?;
         Terminal::clear_terminal()?;
         let (_col, rows) = Terminal::size();
-        let rows = rows - 1;
+        let rows = rows.saturating_sub(1);
 
         for r in 1..rows {
             if r == rows / 3 {
@@ -364,13 +828,14 @@ impl View {
                 let terminal_width = Terminal::size().0 as usize;
                 let msg_len = message.len();
 
-                let padding = (terminal_width - msg_len) / 2;
-                let spaces = " ".repeat(padding - 1);
+                // narrower terminals cut the message off instead
+                let padding = terminal_width.saturating_sub(msg_len) / 2;
+                let spaces = " ".repeat(padding.saturating_sub(1));
 
-                let output = format!("~{spaces}{message}");
-                self.render_line(r, output.as_str(), None)?;
+                let output = format!("{spaces}{message}");
+                self.render_filler_line(r, output.as_str())?;
             } else {
-                self.render_line(r, "~", None)?;
+                self.render_filler_line(r, "")?;
             }
         }
 
@@ -382,6 +847,11 @@ impl View {
             return Ok(());
         }
 
+        self.sync_highlighter();
+        let height = Terminal::size().1 as usize - 1;
+        self.highlighter
+            .highlight_range(self.offset.y..self.offset.y + height, &self.buffer);
+
         if self.buffer.is_empty() {
             self.render_welcome_screen()?;
         } else {
@@ -396,35 +866,320 @@ impl View {
     pub fn load(&mut self, file_path: String) -> Result<(), io::Error> {
         self.buffer.load_lines_from_file(file_path)?;
         self.highlighter.update_file_type(self.buffer.file_type);
-        self.highlighter.highlight_all(&self.buffer.lines);
         self.need_redraw = true;
         Ok(())
     }
 
+    /// Highlights the buffer as the language called `name` from now on, or
+    /// as the detected one when `name` is empty.
+    pub fn set_file_type(&mut self, name: &str) {
+        if name.is_empty() {
+            self.buffer.detect_file_type();
+        } else {
+            match FileType::from_name(name) {
+                Some(file_type) => self.buffer.set_file_type(file_type),
+                None => {
+                    self.message = Some(format!("Unknown file type \"{}\"", name));
+                    self.need_redraw = true;
+                    return;
+                }
+            }
+        }
+
+        self.highlighter.update_file_type(self.buffer.file_type);
+        self.message = Some(format!("File type: {}", self.buffer.file_type.name()));
+        self.need_redraw = true;
+    }
+
+    /// Switches to the theme called `name`.
+    pub fn set_theme(&mut self, name: &str) {
+        if name.is_empty() {
+            return;
+        }
+        self.message = Some(match Themes::get().find(name) {
+            Some(theme) => {
+                self.theme = theme;
+                format!("Theme: {}", theme.name)
+            }
+            None => format!("Unknown theme \"{}\"", name),
+        });
+        self.need_redraw = true;
+    }
+
+    /// Whether some lines past the viewport still await highlighting.
+    pub fn highlight_pending(&self) -> bool {
+        self.highlighter.highlighted_lines() < self.buffer.line_count()
+    }
+
+    /// Highlights the next chunk of lines below the highlighted ones, so the
+    /// rest of the file is ready by the time it scrolls into view. Lines
+    /// shown with provisional highlighting are redrawn once it reaches them.
+    pub fn highlight_idle(&mut self) {
+        self.sync_highlighter();
+        let until = self.highlighter.highlighted_lines() + IDLE_HIGHLIGHT_LINES;
+        if self.highlighter.extend_to(until, &self.buffer) {
+            self.need_redraw = true;
+        }
+    }
+
+    // Hands the buffer's line changes to the highlighter.
+    fn sync_highlighter(&mut self) {
+        for change in self.buffer.take_changes() {
+            self.highlighter.apply_change(change);
+        }
+        self.highlighter.refresh(&self.buffer);
+    }
+
+    /// Saves the buffer to `path`, or to its own file name when `None`, and
+    /// reports the outcome in the status bar.
+    pub fn save(&mut self, path: Option<String>) {
+        let old_file_type = self.buffer.file_type;
+        let result = match &path {
+            Some(path) => self.buffer.save_as(path),
+            None => self.buffer.save_buffer_as_file(),
+        };
+        let name = path
+            .or_else(|| self.buffer.file_name.clone())
+            .unwrap_or_default();
+
+        self.message = Some(match result {
+            Ok(()) => format!("\"{}\" written, {} lines", name, self.buffer.line_count()),
+            Err(err) => format!("Could not save \"{}\": {}", name, err),
+        });
+
+        if old_file_type != self.buffer.file_type {
+            self.highlighter.update_file_type(self.buffer.file_type);
+        }
+        self.need_redraw = true;
+    }
+
+    pub fn start_search(&mut self) {
+        self.search.query.clear();
+        self.search.compile();
+        self.search.active = true;
+        self.search.origin = Some(self.cursor_position());
+        self.selection_anchor = None;
+        self.need_redraw = true;
+    }
+
+    /// Jumps to the first match of the changed query at or after where the
+    /// search started. Returns whether there is one.
+    pub fn update_search(&mut self) -> bool {
+        self.search.compile();
+        let Some(origin) = self.search.origin else {
+            return false;
+        };
+
+        let found = self.search.find_next(&self.buffer, origin, true);
+        match found {
+            Some((start, _)) => self.set_cursor_position(start),
+            None => self.set_cursor_position(origin),
+        }
+        self.need_redraw = true;
+        found.is_some()
+    }
+
+    pub fn toggle_search_case(&mut self) -> bool {
+        self.search.case_sensitive = !self.search.case_sensitive;
+        self.update_search()
+    }
+
+    pub fn search_next(&mut self) {
+        let from = self.buffer.clamp(self.cursor_position());
+        if let Some((start, _)) = self.search.find_next(&self.buffer, from, false) {
+            self.set_cursor_position(start);
+            self.need_redraw = true;
+        }
+    }
+
+    pub fn search_previous(&mut self) {
+        let from = self.buffer.clamp(self.cursor_position());
+        if let Some((start, _)) = self.search.find_previous(&self.buffer, from) {
+            self.set_cursor_position(start);
+            self.need_redraw = true;
+        }
+    }
+
+    /// Closes the search prompt, going back to where it started unless the
+    /// current match is accepted.
+    pub fn end_search(&mut self, accept: bool) {
+        if let Some(origin) = self.search.origin.take()
+            && !accept
+        {
+            self.set_cursor_position(origin);
+        }
+        self.search.active = false;
+        self.buffer.history.seal();
+        self.need_redraw = true;
+    }
+
+    pub fn start_replace(&mut self) {
+        self.replace.stage = Some(ReplaceStage::Pattern);
+        self.replace.pattern.clear();
+        self.replace.replacement.clear();
+        self.replace.count = 0;
+        self.selection_anchor = None;
+        self.need_redraw = true;
+    }
+
+    /// Moves the replace prompt to its next stage. With `replace_all` the
+    /// replacement stage rewrites every match without asking.
+    pub fn submit_replace(&mut self, replace_all: bool) {
+        match self.replace.stage {
+            Some(ReplaceStage::Pattern) if !self.replace.pattern.is_empty() => {
+                self.replace.stage = Some(ReplaceStage::Replacement);
+            }
+            Some(ReplaceStage::Replacement) => {
+                if let Err(err) = self.replace.compile() {
+                    let reason = err.to_string();
+                    let reason = reason.lines().last().unwrap_or_default().trim().to_owned();
+                    self.message = Some(format!("Invalid pattern: {}", reason));
+                    self.replace.stage = None;
+                } else if replace_all {
+                    self.replace_remaining();
+                } else {
+                    self.replace.stage = Some(ReplaceStage::Confirm);
+                    self.select_next_replacement(Position::default(), true);
+                }
+            }
+            _ => {}
+        }
+        self.need_redraw = true;
+    }
+
+    pub fn replace_current(&mut self) {
+        let Some((start, end)) = self.replace.current else {
+            return self.finish_replace();
+        };
+        let Some(line) = self.buffer.line(start.line) else {
+            return self.finish_replace();
+        };
+
+        let text = self.replace.expand(&line, start.byte);
+        let cursor = self.cursor_position();
+        let new_end = self.buffer.replace(start, end, &text, cursor);
+        self.replace.count += 1;
+
+        self.select_next_replacement(new_end, start != end);
+    }
+
+    pub fn skip_replacement(&mut self) {
+        match self.replace.current {
+            Some((start, end)) => self.select_next_replacement(end, start != end),
+            None => self.finish_replace(),
+        }
+    }
+
+    /// Replaces the current match and every one after it in one undo step.
+    pub fn replace_remaining(&mut self) {
+        let from = self
+            .replace
+            .current
+            .map(|(start, _)| start)
+            .unwrap_or_default();
+        let cursor = self.cursor_position();
+
+        self.buffer.history.begin_group();
+        let mut line_idx = from.line;
+        while let Some(line) = self.buffer.line(line_idx) {
+            let line = line.into_owned();
+            let from_byte = if line_idx == from.line { from.byte } else { 0 };
+            let (replaced, count) = self.replace.replace_in_line(&line, from_byte);
+
+            if count > 0 {
+                let start = Position {
+                    line: line_idx,
+                    byte: 0,
+                };
+                let end = Position {
+                    line: line_idx,
+                    byte: line.len(),
+                };
+                self.buffer.replace(start, end, &replaced, cursor);
+                self.replace.count += count;
+            }
+            line_idx += replaced.matches('\n').count() + 1;
+        }
+        self.buffer.history.end_group();
+
+        self.set_cursor_position(self.buffer.clamp(cursor));
+        self.finish_replace();
+    }
+
+    /// Leaves the replace prompt and reports how many matches were replaced.
+    pub fn finish_replace(&mut self) {
+        let count = self.replace.count;
+        let plural = if count == 1 { "" } else { "s" };
+
+        self.message = match self.replace.stage {
+            Some(ReplaceStage::Confirm) | Some(ReplaceStage::Replacement) => {
+                Some(format!("Replaced {} occurrence{}", count, plural))
+            }
+            _ => None,
+        };
+        self.replace.stage = None;
+        self.replace.current = None;
+        self.selection_anchor = None;
+        self.buffer.history.seal();
+        self.need_redraw = true;
+    }
+
+    // Highlights the next match at or after `from` by selecting it, or ends
+    // the replace once the end of the buffer is reached.
+    fn select_next_replacement(&mut self, from: Position, inclusive: bool) {
+        match self.replace.find_from(&self.buffer, from, inclusive) {
+            Some((start, end)) => {
+                self.replace.current = Some((start, end));
+                self.selection_anchor = Some(end);
+                self.set_cursor_position(start);
+                self.need_redraw = true;
+            }
+            None => self.finish_replace(),
+        }
+    }
+
+    fn render_prompt(&self, text: &str) -> Result<(), io::Error> {
+        let height = Terminal::size().1;
+
+        Terminal::move_cursor_to(0, height - 1)?;
+        Terminal::clear_line()?;
+        Terminal::print(text)?;
+
+        Ok(())
+    }
+
     fn render_status_bar(&self) -> Result<(), io::Error> {
+        if self.replace.stage == Some(ReplaceStage::Confirm) {
+            return self.render_prompt("Replace this match? (y)es (n)o (a)ll (q)uit");
+        }
+
         let (width, height) = Terminal::size();
         let height = height as usize;
 
         // Move to status bar position (second to last row)
         Terminal::move_cursor_to(0, (height - 1) as u16)?;
+        Terminal::set_style(&self.theme.text.patch(self.theme.status_bar))?;
         Terminal::clear_line()?;
 
-        // Set inverted colors for status bar
-        Terminal::queue_command(SetAttribute(Attribute::Reverse))?;
-
-        // Left side: filename or [No Name]
-        let filename = self
-            .buffer
-            .file_name
-            .as_ref()
-            .map(|s| s.as_str())
-            .unwrap_or("[No Name]");
+        // Left side: status message, or filename (or [No Name]) with a
+        // [+] marker while there are unsaved changes
+        let filename = match &self.message {
+            Some(message) => message.clone(),
+            None => {
+                let name = self.buffer.file_name.as_deref().unwrap_or("[No Name]");
+                if self.buffer.modified {
+                    format!("{} [+]", name)
+                } else {
+                    name.to_owned()
+                }
+            }
+        };
 
         // Middle: file type
         let file_type = self.buffer.file_type.name();
 
         // Right side: cursor position and line count
-        let line_count = self.buffer.lines.len();
+        let line_count = self.buffer.line_count();
         let current_line = self.cursor_location.y + 1; // 1-indexed for display
         let current_col = self.cursor_location.x + 1;
 
@@ -440,16 +1195,96 @@ impl View {
         // Calculate spacing
         let left_len = filename.len();
         let right_len = right_status.len();
-        let padding = width as usize - left_len - right_len;
+        let padding = (width as usize).saturating_sub(left_len + right_len);
 
         // Print status bar
-        Terminal::print(filename)?;
+        Terminal::print(&filename)?;
         Terminal::print(" ".repeat(padding))?;
         Terminal::print(&right_status)?;
 
-        // Reset attributes
-        Terminal::queue_command(SetAttribute(Attribute::Reset))?;
+        Terminal::set_style(&self.theme.text)?;
 
         Ok(())
     }
 }
+
+// Width of `text` on screen, measured grapheme by grapheme like the
+// renderer does.
+fn display_width(text: &str) -> usize {
+    text.graphemes(true)
+        .fold(0, |column, g| column + grapheme_width(g, column))
+}
+
+// Columns `g` takes when drawn at display `column` of a line, or of a row
+// when soft wrapping. A tab reaches the next tab stop.
+fn grapheme_width(g: &str, column: usize) -> usize {
+    if g == "\t" {
+        TAB_WIDTH - column % TAB_WIDTH
+    } else {
+        g.width()
+    }
+}
+
+/// Byte offsets where the visual rows of `line` start when it is wrapped to
+/// `width` columns, the first one being 0. Rows break after whitespace, and
+/// inside a word only when it does not fit on a row of its own. Rows after
+/// the first are narrower by the wrap indicator.
+fn wrap_rows(line: &str, width: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut available = width;
+    let mut used = 0;
+    // where the row may break after whitespace, and the width up to there
+    let mut last_break = None;
+
+    for (byte_idx, g) in line.grapheme_indices(true) {
+        let w = grapheme_width(g, used);
+        let blank = g.chars().all(char::is_whitespace);
+
+        // whitespace may hang past the edge rather than start a row
+        while !blank && used > 0 && used + w > available {
+            match last_break.take() {
+                Some((start, before)) => {
+                    starts.push(start);
+                    used -= before;
+                }
+                None => {
+                    starts.push(byte_idx);
+                    used = 0;
+                }
+            }
+            available = width.saturating_sub(WRAP_INDICATOR.width()).max(1);
+        }
+
+        used += w;
+        if blank {
+            last_break = Some((byte_idx + g.len(), used));
+        }
+    }
+
+    starts
+}
+
+// Byte offset of the grapheme at screen `column` on visual row `row` of
+// `line`, or of the last one when the row is shorter. Only the last row
+// reaches past its last grapheme, to the line end.
+fn byte_at_column(line: &str, rows: &[usize], row: usize, column: usize) -> usize {
+    let start = rows[row];
+    let end = rows.get(row + 1).copied();
+    let indent = if row > 0 { WRAP_INDICATOR.width() } else { 0 };
+    let mut current = indent;
+    let mut last = start;
+
+    for (offset, g) in line[start..end.unwrap_or(line.len())].grapheme_indices(true) {
+        let w = grapheme_width(g, current - indent);
+        if current + w > column {
+            return start + offset;
+        }
+        current += w;
+        last = start + offset;
+    }
+
+    match end {
+        Some(_) => last,
+        None => line.len(),
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    env, fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    process,
+};
+
+use crossterm::event::{Event, KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use editra::{
+    editor::Editor,
+    highlighter::HighlightType,
+    terminal::{MemoryBackend, Terminal},
+    theme::{Style, Themes},
+};
+
+// A file holding `contents`, removed when dropped.
+struct TestFile(PathBuf);
+
+impl TestFile {
+    fn new(name: &str, contents: &str) -> Self {
+        let path = env::temp_dir().join(format!("editra-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        TestFile(path)
+    }
+
+    fn path(&self) -> String {
+        self.0.display().to_string()
+    }
+
+    fn contents(&self) -> String {
+        fs::read_to_string(&self.0).unwrap()
+    }
+}
+
+impl Drop for TestFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Opens `file` in an editor on `backend` and handles the queued events,
+// leaving the last frame on the backend.
+fn run(file: &TestFile, backend: &MemoryBackend) {
+    run_with_args(backend, &[&file.path()]);
+}
+
+// Starts the editor on `backend` with the command line arguments `args`.
+fn run_with_args(backend: &MemoryBackend, args: &[&str]) {
+    let error = start(backend, args).unwrap_err();
+    // the editor stops once it runs out of events
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+}
+
+// Runs the editor on `backend` with `args` until it quits or fails.
+fn start(backend: &MemoryBackend, args: &[&str]) -> io::Result<()> {
+    Terminal::set_backend(backend.clone());
+    let args = ["editra"].iter().chain(args).map(|arg| arg.to_string());
+    Editor::default().run_with_args(args)
+}
+
+fn ctrl(backend: &MemoryBackend, c: char) {
+    backend.push_key(KeyCode::Char(c), KeyModifiers::CONTROL);
+}
+
+fn alt(backend: &MemoryBackend, c: char) {
+    backend.push_key(KeyCode::Char(c), KeyModifiers::ALT);
+}
+
+fn enter(backend: &MemoryBackend) {
+    backend.push_key(KeyCode::Enter, KeyModifiers::NONE);
+}
+
+fn click(backend: &MemoryBackend, column: u16, row: u16) {
+    let event = MouseEvent {
+        kind: MouseEventKind::Down(MouseButton::Left),
+        column,
+        row,
+        modifiers: KeyModifiers::NONE,
+    };
+    backend.push_event(Event::Mouse(event));
+}
+
+// Style of the cell at `x`, `y`.
+fn style(backend: &MemoryBackend, x: u16, y: u16) -> Style {
+    backend.cell(x, y).unwrap().1
+}
+
+#[test]
+fn typing_inserts_at_the_cursor() {
+    let file = TestFile::new("typing.txt", "hello\nworld\n");
+    let backend = MemoryBackend::new(40, 10);
+    backend.push_text("abc");
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "abchello");
+    assert_eq!(backend.line(1), "world");
+    assert_eq!(backend.cursor(), Some((3, 0)));
+}
+
+#[test]
+fn ctrl_z_undoes_typing() {
+    let file = TestFile::new("undo.txt", "hello\n");
+    let backend = MemoryBackend::new(40, 10);
+    backend.push_text("abc");
+    backend.push_key(KeyCode::Char('z'), KeyModifiers::CONTROL);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "hello");
+    assert_eq!(backend.cursor(), Some((0, 0)));
+}
+
+#[test]
+fn ctrl_x_cuts_the_selection() {
+    let file = TestFile::new("cut.txt", "hello\n");
+    let backend = MemoryBackend::new(40, 10);
+    backend.push_key(KeyCode::Right, KeyModifiers::SHIFT);
+    backend.push_key(KeyCode::Right, KeyModifiers::SHIFT);
+    backend.push_key(KeyCode::Char('x'), KeyModifiers::CONTROL);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "llo");
+    assert_eq!(backend.cursor(), Some((0, 0)));
+}
+
+#[test]
+fn ctrl_v_pastes_what_was_cut() {
+    let file = TestFile::new("paste.txt", "hello\n");
+    let backend = MemoryBackend::new(40, 10);
+    backend.push_key(KeyCode::Right, KeyModifiers::SHIFT);
+    backend.push_key(KeyCode::Right, KeyModifiers::SHIFT);
+    backend.push_key(KeyCode::Char('x'), KeyModifiers::CONTROL);
+    backend.push_key(KeyCode::End, KeyModifiers::NONE);
+    backend.push_key(KeyCode::Char('v'), KeyModifiers::CONTROL);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "llohe");
+    assert_eq!(backend.cursor(), Some((5, 0)));
+}
+
+#[test]
+fn resizing_redraws_the_status_bar_on_the_last_row() {
+    let file = TestFile::new("resize.txt", "hello\n");
+    let backend = MemoryBackend::new(40, 10);
+    backend.resize(30, 6);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.lines().len(), 6);
+    assert_eq!(backend.line(0), "hello");
+    assert!(backend.line(5).contains("editra-"));
+}
+
+#[test]
+fn tabs_reach_the_next_tab_stop() {
+    let file = TestFile::new("tabs.txt", "\tx\nab\tcd\n");
+    let backend = MemoryBackend::new(40, 10);
+    backend.push_key(KeyCode::Right, KeyModifiers::NONE);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "        x");
+    assert_eq!(backend.line(1), "ab      cd");
+    assert_eq!(backend.cursor(), Some((8, 0)));
+}
+
+#[test]
+fn clicking_on_a_tab_puts_the_cursor_before_it() {
+    let file = TestFile::new("tab-click.txt", "ab\tcd\n");
+    let backend = MemoryBackend::new(40, 10);
+    click(&backend, 5, 0);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.cursor(), Some((2, 0)));
+}
+
+#[test]
+fn welcome_screen_fits_a_narrow_terminal() {
+    let backend = MemoryBackend::new(20, 10);
+
+    run_with_args(&backend, &[]);
+
+    assert!(backend.line(3).starts_with("~Welcome to editra"));
+    assert_eq!(backend.line(1), "~");
+}
+
+#[test]
+fn search_jumps_to_the_first_match() {
+    let file = TestFile::new("search.txt", "one\ntwo\nthree two\n");
+    let backend = MemoryBackend::new(40, 10);
+    ctrl(&backend, 'f');
+    backend.push_text("two");
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(9), "Search [aa]: two");
+    assert_eq!(backend.cursor(), Some((16, 9)));
+    // both matches are marked, the rest of the text is not
+    assert_ne!(style(&backend, 0, 1), style(&backend, 0, 0));
+    assert_eq!(style(&backend, 6, 2), style(&backend, 0, 1));
+}
+
+#[test]
+fn search_accepts_the_match_with_enter() {
+    let file = TestFile::new("search-enter.txt", "one\ntwo\nthree two\n");
+    let backend = MemoryBackend::new(40, 10);
+    ctrl(&backend, 'f');
+    backend.push_text("TWO");
+    enter(&backend);
+    backend.push_key(KeyCode::F(3), KeyModifiers::NONE);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.cursor(), Some((6, 2)));
+}
+
+#[test]
+fn search_hints_when_nothing_matches() {
+    let file = TestFile::new("search-none.txt", "one\ntwo\n");
+    let backend = MemoryBackend::new(40, 10);
+    ctrl(&backend, 'f');
+    backend.push_text("six");
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(9), "Search [aa]: six (no match)");
+}
+
+#[test]
+fn replace_all_rewrites_every_match() {
+    let file = TestFile::new("replace-all.txt", "a b a\nb a\n");
+    let backend = MemoryBackend::new(40, 10);
+    ctrl(&backend, 'r');
+    backend.push_text("a");
+    enter(&backend);
+    backend.push_text("x");
+    backend.push_key(KeyCode::Enter, KeyModifiers::ALT);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "x b x");
+    assert_eq!(backend.line(1), "b x");
+    assert!(backend.line(9).starts_with("Replaced 3 occurrences"));
+}
+
+#[test]
+fn replace_asks_for_each_match() {
+    let file = TestFile::new("replace-confirm.txt", "a b a a\n");
+    let backend = MemoryBackend::new(40, 10);
+    ctrl(&backend, 'r');
+    backend.push_text("a");
+    enter(&backend);
+    backend.push_text("x");
+    enter(&backend);
+    backend.push_text("nya");
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "a b x x");
+    assert!(backend.line(9).starts_with("Replaced 2 occurrences"));
+}
+
+#[test]
+fn ctrl_q_asks_again_with_unsaved_changes() {
+    let file = TestFile::new("quit-unsaved.txt", "hello\n");
+    let backend = MemoryBackend::new(60, 10);
+    backend.push_text("x");
+    ctrl(&backend, 'q');
+
+    run(&file, &backend);
+
+    assert!(
+        backend
+            .line(9)
+            .starts_with("Unsaved changes! Press Ctrl+Q again to quit")
+    );
+}
+
+#[test]
+fn ctrl_q_quits_after_saving() {
+    let file = TestFile::new("quit-saved.txt", "hello\n");
+    let backend = MemoryBackend::new(60, 10);
+    backend.push_text("x");
+    ctrl(&backend, 's');
+    ctrl(&backend, 'q');
+
+    start(&backend, &[&file.path()]).unwrap();
+
+    assert_eq!(file.contents(), "xhello\n");
+    assert_eq!(backend.line(0), "Goodbye");
+}
+
+#[test]
+fn undoing_back_to_the_save_point_clears_the_marker() {
+    let file = TestFile::new("save-point.txt", "hello\n");
+    let backend = MemoryBackend::new(60, 10);
+    backend.push_text("x");
+    ctrl(&backend, 's');
+    backend.push_text("y");
+    ctrl(&backend, 'z');
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "xhello");
+    assert!(!backend.line(9).contains("[+]"));
+    assert!(backend.line(9).starts_with(&file.path()));
+}
+
+#[test]
+fn undoing_past_the_save_point_marks_changes() {
+    let file = TestFile::new("past-save-point.txt", "hello\n");
+    let backend = MemoryBackend::new(60, 10);
+    backend.push_text("x");
+    ctrl(&backend, 's');
+    ctrl(&backend, 'z');
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "hello");
+    assert!(backend.line(9).starts_with(&format!("{} [+]", file.path())));
+}
+
+#[test]
+fn syntax_is_highlighted_with_the_theme() {
+    let file = TestFile::new("highlight.rs", "fn main() {}\n");
+    let backend = MemoryBackend::new(40, 10);
+
+    run(&file, &backend);
+
+    let theme = Themes::get().initial();
+    assert_eq!(
+        style(&backend, 0, 0),
+        theme.syntax_style(HighlightType::Keyword)
+    );
+    assert_eq!(
+        style(&backend, 1, 0),
+        theme.syntax_style(HighlightType::Keyword)
+    );
+    assert_eq!(style(&backend, 2, 0), theme.text);
+}
+
+#[test]
+fn alt_w_wraps_long_lines() {
+    let file = TestFile::new("wrap.txt", "aaaa bbbb cccc dddd eeee\nf\n");
+    let backend = MemoryBackend::new(20, 10);
+    alt(&backend, 'w');
+    backend.push_key(KeyCode::End, KeyModifiers::NONE);
+
+    run(&file, &backend);
+
+    assert_eq!(backend.line(0), "aaaa bbbb cccc dddd");
+    assert_eq!(backend.line(1), "↪eeee");
+    assert_eq!(backend.line(2), "f");
+    assert_eq!(backend.cursor(), Some((5, 1)));
+}
+
+#[test]
+fn alt_t_switches_the_theme() {
+    let file = TestFile::new("theme.txt", "hello\n");
+    let backend = MemoryBackend::new(40, 10);
+    alt(&backend, 't');
+    backend.push_text("light");
+    enter(&backend);
+
+    run(&file, &backend);
+
+    let light = Themes::get().find("light").unwrap();
+    assert!(backend.line(9).starts_with("Theme: Light"));
+    assert_eq!(style(&backend, 0, 0), light.text);
+    assert_eq!(style(&backend, 0, 9), light.text.patch(light.status_bar));
+}
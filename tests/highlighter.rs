@@ -0,0 +1,40 @@
+use editra::{
+    buffer::{Buffer, Position},
+    highlighter::{FileType, HighlightType, Highlighter},
+};
+
+// Highlights `text` as `file_type` and gives the highlight types of each
+// line, in order.
+fn highlight(file_type: &str, text: &str) -> Vec<Vec<HighlightType>> {
+    let mut buffer = Buffer::default();
+    buffer.insert(Position::default(), text);
+    let mut highlighter = Highlighter::new(FileType::from_name(file_type).unwrap());
+    highlighter.extend_to(buffer.line_count(), &buffer);
+
+    (0..buffer.line_count())
+        .map(|line_idx| {
+            highlighter
+                .get_annotations(line_idx)
+                .map_or_else(Vec::new, |annotations| {
+                    annotations.iter().map(|a| a.highlight_type).collect()
+                })
+        })
+        .collect()
+}
+
+#[test]
+fn toml_table_headers_are_headings() {
+    let lines = highlight("toml", "[package]\n[[bin]]\n");
+
+    assert_eq!(lines[0], [HighlightType::Heading]);
+    assert_eq!(lines[1], [HighlightType::Heading]);
+}
+
+#[test]
+fn toml_multi_line_array_ends_are_not_headings() {
+    let lines = highlight("toml", "a = [\n  \"b\"]\nc = [\n  true]\n");
+
+    assert!(!lines[1].contains(&HighlightType::Heading));
+    assert!(!lines[3].contains(&HighlightType::Heading));
+    assert!(lines[1].contains(&HighlightType::String));
+}
@@ -0,0 +1,47 @@
+use editra::replace::Replace;
+
+fn replace(pattern: &str, replacement: &str, regex_mode: bool) -> Replace {
+    let mut replace = Replace::default();
+    replace.pattern = pattern.to_owned();
+    replace.replacement = replacement.to_owned();
+    replace.regex_mode = regex_mode;
+    replace.case_sensitive = true;
+    replace.compile().unwrap();
+    replace
+}
+
+#[test]
+fn replaces_every_match_in_the_line() {
+    let replace = replace("ab", "x", false);
+
+    assert_eq!(
+        replace.replace_in_line("ab ab ab", 0),
+        ("x x x".to_owned(), 3)
+    );
+}
+
+#[test]
+fn matching_starts_at_from_byte() {
+    let replace = replace("aa", "x", false);
+
+    // from the start the match would be `aa` at 0, hiding the one at 1
+    assert_eq!(replace.replace_in_line("aaa", 1), ("ax".to_owned(), 1));
+    assert_eq!(replace.replace_in_line("aaaa", 1), ("axa".to_owned(), 1));
+}
+
+#[test]
+fn regex_mode_expands_references() {
+    let replace = replace(r"(\w+)=(\w+)", "$2=$1", true);
+
+    assert_eq!(
+        replace.replace_in_line("a=b c=d", 2),
+        ("a=b d=c".to_owned(), 1)
+    );
+}
+
+#[test]
+fn empty_matches_are_replaced_once_each() {
+    let replace = replace("x*", "-", true);
+
+    assert_eq!(replace.replace_in_line("axb", 0), ("-a-b-".to_owned(), 3));
+}